@@ -0,0 +1,225 @@
+//! Arbitrary output variables: images of the first surface seen through
+//! each pixel, rendered along with the beauty pass for denoisers and
+//! compositing.
+
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    image::{exr, pfm},
+    integrator::debug::id_color,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+};
+
+/// Auxiliary images, row by row from the top left corner. Every image is
+/// averaged over the samples of its pixel.
+pub struct Aovs {
+    pub width: usize,
+    pub height: usize,
+    /// Shading normal of the first hit
+    pub normal: Vec<Color>,
+    /// Base color of the material at the first hit
+    pub albedo: Vec<Color>,
+    /// Distance from the camera to the first hit along the direction it
+    /// looks in, infinite when every sample missed
+    pub depth: Vec<f32>,
+    /// World position of the first hit
+    pub position: Vec<Point3>,
+    /// A color unique to the object at the first hit. Taken from a single
+    /// sample, since averages of IDs are meaningless.
+    pub object_id: Vec<Color>,
+    /// Per channel variance of the beauty pass pixel
+    pub variance: Vec<Color>,
+}
+
+impl Aovs {
+    pub(crate) fn new(width: usize, height: usize, pixels: Vec<PixelAovs>) -> Self {
+        let mut aovs = Aovs {
+            width,
+            height,
+            normal: Vec::with_capacity(pixels.len()),
+            albedo: Vec::with_capacity(pixels.len()),
+            depth: Vec::with_capacity(pixels.len()),
+            position: Vec::with_capacity(pixels.len()),
+            object_id: Vec::with_capacity(pixels.len()),
+            variance: Vec::with_capacity(pixels.len()),
+        };
+        for pixel in pixels {
+            aovs.variance.push(pixel.variance());
+            let samples = pixel.samples.max(1) as f32;
+            let hits = pixel.hits.max(1) as f32;
+            aovs.normal.push(Color::from(pixel.normal / samples));
+            aovs.albedo.push(pixel.albedo * (1. / samples));
+            aovs.depth.push(if pixel.hits == 0 {
+                f32::INFINITY
+            } else {
+                pixel.depth / hits
+            });
+            aovs.position.push(pixel.position / hits);
+            aovs.object_id
+                .push(pixel.object_id.unwrap_or_else(Color::black));
+        }
+        aovs
+    }
+
+    /// Three channel images along with their names. Depth is repeated in
+    /// every channel.
+    pub fn layers(&self) -> Vec<(&'static str, Vec<Color>)> {
+        vec![
+            ("normal", self.normal.clone()),
+            ("albedo", self.albedo.clone()),
+            (
+                "depth",
+                self.depth.iter().map(|d| Color::new(*d, *d, *d)).collect(),
+            ),
+            (
+                "position",
+                self.position.iter().map(|p| Color::from(*p)).collect(),
+            ),
+            ("object_id", self.object_id.clone()),
+            ("variance", self.variance.clone()),
+        ]
+    }
+
+    /// Every image as EXR channels, in layers named after the images.
+    /// Depth is a single `Z` channel.
+    pub fn exr_channels(&self) -> Vec<exr::Channel> {
+        let mut channels = Vec::new();
+        for (name, pixels) in self.layers() {
+            if name == "depth" {
+                channels.push(exr::Channel {
+                    name: "depth.Z".to_string(),
+                    values: self.depth.clone(),
+                });
+            } else {
+                channels.extend(exr::rgb_channels(name, &pixels));
+            }
+        }
+        channels
+    }
+
+    /// Writes every image as its own PFM file next to `beauty`, so
+    /// `render.ppm` gets `render.normal.pfm` and so on
+    pub fn write_pfm(&self, beauty: &Path) -> io::Result<()> {
+        for (name, pixels) in self.layers() {
+            let path = beauty.with_extension(format!("{name}.pfm"));
+            let mut out = BufWriter::new(File::create(path)?);
+            if name == "depth" {
+                pfm::write_gray(&mut out, self.width, self.height, &self.depth)?;
+            } else {
+                pfm::write(&mut out, self.width, self.height, &pixels)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Running sums over the samples of a single pixel
+pub(crate) struct PixelAovs {
+    samples: usize,
+    hits: usize,
+    normal: Vec3,
+    albedo: Color,
+    depth: f32,
+    position: Vec3,
+    object_id: Option<Color>,
+    sum: Color,
+    sum_squares: Color,
+}
+
+impl PixelAovs {
+    pub fn new() -> Self {
+        PixelAovs {
+            samples: 0,
+            hits: 0,
+            normal: Vec3(0., 0., 0.),
+            albedo: Color::black(),
+            depth: 0.,
+            position: Vec3(0., 0., 0.),
+            object_id: None,
+            sum: Color::black(),
+            sum_squares: Color::black(),
+        }
+    }
+
+    /// Records the first surface hit by a camera ray, from a camera looking
+    /// towards `forward`
+    pub fn add_hit(&mut self, r: &Ray, hit: Option<&HitRecord>, forward: &Vec3) {
+        let Some(rec) = hit else {
+            return;
+        };
+        self.hits += 1;
+        self.normal += rec.normal;
+        self.albedo += rec.material.albedo(rec);
+        self.depth += (rec.p - r.origin).dot(forward);
+        self.position += rec.p;
+        self.object_id
+            .get_or_insert_with(|| id_color(rec.object_id));
+    }
+
+    /// Records the color the integrator found for the camera ray
+    pub fn add_sample(&mut self, color: &Color) {
+        self.samples += 1;
+        self.sum += color.clone();
+        self.sum_squares += color.clone() * color.clone();
+    }
+
+    /// Variance of the average of the samples
+    fn variance(&self) -> Color {
+        if self.samples < 2 {
+            return Color::black();
+        }
+        let n = self.samples as f32;
+        let mut variance = Color::black();
+        for channel in 0..3 {
+            let mean = self.sum[channel] / n;
+            let sample_variance = (self.sum_squares[channel] / n - mean * mean) * n / (n - 1.);
+            variance[channel] = sample_variance.max(0.) / n;
+        }
+        variance
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        hittable::{Hittable, HittableList},
+        material::Lambertian,
+        sphere::Sphere,
+    };
+
+    #[test]
+    fn first_hit_averaged() {
+        let mut world = HittableList::default();
+        world.push(Sphere::new(
+            Vec3(0., 0., -2.),
+            0.5,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ));
+        let mut pixel = PixelAovs::new();
+        let hit = Ray::new(Vec3(0., 0., -1.), Vec3(0., 0., 0.));
+        let miss = Ray::new(Vec3(0., 1., 0.), Vec3(0., 0., 0.));
+        let forward = Vec3(0., 0., -1.);
+        for (r, color) in [(hit, 1.), (miss, 3.)] {
+            let rec = world.hit(&r, &(0.001..f32::INFINITY));
+            pixel.add_hit(&r, rec.as_ref(), &forward);
+            pixel.add_sample(&Color::new(color, color, color));
+        }
+
+        let aovs = Aovs::new(1, 1, vec![pixel]);
+        assert_eq!(aovs.normal[0], Color::new(0., 0., 0.5));
+        assert_eq!(aovs.albedo[0], Color::new(0.25, 0.25, 0.25));
+        // Depth and position only count the samples that hit something
+        assert_eq!(aovs.depth[0], 1.5);
+        assert_eq!(aovs.position[0], Vec3(0., 0., -1.5));
+        assert_ne!(aovs.object_id[0], Color::black());
+        // Samples 1 and 3 have a variance of 2, and their mean half of that
+        assert_eq!(aovs.variance[0], Color::new(1., 1., 1.));
+    }
+}
@@ -1,25 +1,35 @@
 use crate::{
+    aov::{Aovs, PixelAovs},
     color::Color,
-    hittable::Hittable,
+    hittable::{Hittable, HittableList},
+    image::Image,
+    integrator::{path::PathTracer, Integrator, Scene},
     ray::{self},
+    sky::{Background, Gradient},
     vec3::{self, Point3, Vec3},
 };
-use indicatif::{ParallelProgressIterator, ProgressBar, ProgressIterator, ProgressStyle};
+use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
 use rand::{self, Rng};
 use rayon::prelude::*;
-use std::f32::INFINITY;
+use std::{fmt, time};
 
 pub struct CameraBuilder {
     pub aspect_ratio: f32,
     pub image_width: usize,
     pub samples_per_pixel: usize,
-    pub reflection_depth: usize,
     pub vfov: f32,
     pub look_from: Point3,
     pub look_to: Point3,
     pub vup: Vec3,
     pub defocus_angle: f32,
     pub focus_distance: f32,
+    pub background: Box<dyn Background>,
+    pub integrator: Box<dyn Integrator>,
+    /// Also render the auxiliary images of the first hits
+    pub aovs: bool,
+    /// Leave the background out of the image, recording in an alpha channel
+    /// how much of each pixel is covered by objects
+    pub alpha: bool,
 }
 impl Default for CameraBuilder {
     fn default() -> Self {
@@ -27,13 +37,16 @@ impl Default for CameraBuilder {
             aspect_ratio: 16. / 9.,
             image_width: 1200,
             samples_per_pixel: 100,
-            reflection_depth: 50,
             vfov: 90.,
             look_from: Vec3::new(0., 0., 0.),
             look_to: Vec3::new(0., 0., -1.),
             vup: Vec3::new(0., 1., 0.),
             defocus_angle: 0.,
             focus_distance: 10.,
+            background: Box::new(Gradient::default()),
+            integrator: Box::new(PathTracer::default()),
+            aovs: false,
+            alpha: false,
         }
     }
 }
@@ -88,24 +101,59 @@ impl CameraBuilder {
             pixel_delta_u,
             pixel_delta_v,
             center,
+            forward: -w,
             pixel_sample_scale,
-            reflection_depth: self.reflection_depth,
             lens_dimensions,
+            background: self.background,
+            integrator: self.integrator,
+            aovs: self.aovs,
+            alpha: self.alpha,
         }
     }
 }
 
+/// Summary of a finished render
+pub struct RenderStats {
+    paths: usize,
+    bounces: usize,
+    elapsed: time::Duration,
+}
+
+impl RenderStats {
+    /// Average number of surfaces hit by a path
+    pub fn average_path_length(&self) -> f32 {
+        (self.bounces as f32) / (self.paths as f32)
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Traced {} paths in {:.2?}, average path length {:.2}",
+            self.paths,
+            self.elapsed,
+            self.average_path_length()
+        )
+    }
+}
+
 pub struct Camera {
     image_width: usize,
     samples_per_pixel: usize,
-    reflection_depth: usize,
     image_height: usize,
     pixel00: Vec3,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
     center: Vec3,
+    /// Direction the camera looks in
+    forward: Vec3,
     pixel_sample_scale: f32,
     lens_dimensions: Option<(Vec3, Vec3)>,
+    background: Box<dyn Background>,
+    integrator: Box<dyn Integrator>,
+    aovs: bool,
+    alpha: bool,
 }
 
 impl Camera {
@@ -113,10 +161,41 @@ impl Camera {
     pub fn builder() -> CameraBuilder {
         CameraBuilder::default()
     }
-    /// Renders the scene with side-effects going straight to stdout
-    /// A buffer writer would improve the performance of this function
-    pub fn render(&mut self, world: &impl Hittable) {
-        // render
+    /// Renders the scene, along with the auxiliary images when the camera
+    /// was built with them
+    ///
+    /// Every emissive object in `world` should also be in `lights`, so it
+    /// can be sampled directly instead of found by chance.
+    pub fn render(
+        &mut self,
+        world: &impl Hittable,
+        lights: &HittableList,
+    ) -> (Image, Option<Aovs>) {
+        let (image, aovs, stats) = self.render_samples(world, lights);
+        eprintln!("{stats}");
+        (image, aovs)
+    }
+
+    /// Computes the averaged color of every pixel, row by row from the top
+    /// left corner
+    #[cfg(test)]
+    fn render_pixels(
+        &mut self,
+        world: &impl Hittable,
+        lights: &HittableList,
+    ) -> (Vec<Color>, RenderStats) {
+        let (image, _, stats) = self.render_samples(world, lights);
+        (image.pixels, stats)
+    }
+
+    /// Computes the averaged color of every pixel, along with the auxiliary
+    /// images if enabled
+    fn render_samples(
+        &mut self,
+        world: &impl Hittable,
+        lights: &HittableList,
+    ) -> (Image, Option<Aovs>, RenderStats) {
+        let start = time::Instant::now();
         let bar = ProgressBar::new(self.image_height as u64);
         let prog_style = ProgressStyle::with_template(
             "[{elapsed_precise}] {wide_bar:.cyan/blue} {pos:>7}/{len:7} {msg} [eta {eta_precise}]",
@@ -125,32 +204,76 @@ impl Camera {
         .tick_chars("#+-");
         bar.set_style(prog_style);
 
-        println!("P3\n{} {}\n255", self.image_width, self.image_height);
+        let scene = Scene {
+            world,
+            lights,
+            background: self.background.as_ref(),
+        };
+        self.integrator.preprocess(&scene);
 
-        let pixels = (0..self.image_height)
+        let samples: Vec<(Color, usize, usize, Option<PixelAovs>)> = (0..self.image_height)
             .into_par_iter()
             .progress_with(bar)
             .flat_map(|j| {
                 (0..self.image_width)
                     .map(|i| {
-                        (0..self.samples_per_pixel)
-                            .map(|_| {
-                                let r = self.get_ray(i, j);
-                                Camera::ray_color(r, self.reflection_depth, world)
-                            })
-                            .sum::<Color>()
+                        let mut color = Color::black();
+                        let mut covered = 0;
+                        let mut bounces = 0;
+                        let mut aovs = self.aovs.then(PixelAovs::new);
+                        for _ in 0..self.samples_per_pixel {
+                            let r = self.get_ray(i, j);
+                            // Looked up once for the integrator and the
+                            // auxiliary images
+                            let hit = scene.world.hit(&r, &(0.001..f32::INFINITY));
+                            if let Some(aovs) = &mut aovs {
+                                aovs.add_hit(&r, hit.as_ref(), &self.forward);
+                            }
+                            // Rays that miss everything are transparent
+                            if self.alpha && hit.is_none() {
+                                if let Some(aovs) = &mut aovs {
+                                    aovs.add_sample(&Color::black());
+                                }
+                                continue;
+                            }
+                            covered += 1;
+                            let (sample, n) = self.integrator.ray_color_with_hit(r, hit, &scene);
+                            if let Some(aovs) = &mut aovs {
+                                aovs.add_sample(&sample);
+                            }
+                            color += sample;
+                            bounces += n;
+                        }
+                        (self.pixel_sample_scale * color, covered, bounces, aovs)
                     })
-                    .collect::<Vec<Color>>()
+                    .collect::<Vec<_>>()
             })
-            .collect::<Vec<Color>>();
+            .collect();
 
-        let spinner = ProgressBar::new_spinner();
-        spinner.enable_steady_tick(std::time::Duration::from_millis(100));
-
-        pixels
-            .iter()
-            .progress_with(spinner)
-            .for_each(|e| println!("{}", self.pixel_sample_scale * e));
+        // Samples left out by the alpha channel trace no path
+        let stats = RenderStats {
+            paths: samples.iter().map(|(_, covered, _, _)| covered).sum(),
+            bounces: samples.iter().map(|(_, _, bounces, _)| bounces).sum(),
+            elapsed: start.elapsed(),
+        };
+        let mut pixels = Vec::with_capacity(samples.len());
+        let mut alpha = Vec::with_capacity(samples.len());
+        let mut pixel_aovs = Vec::with_capacity(samples.len());
+        for (color, covered, _, aovs) in samples {
+            pixels.push(color);
+            alpha.push(covered as f32 * self.pixel_sample_scale);
+            pixel_aovs.extend(aovs);
+        }
+        let image = Image {
+            width: self.image_width,
+            height: self.image_height,
+            pixels,
+            alpha: self.alpha.then_some(alpha),
+        };
+        let aovs = self
+            .aovs
+            .then(|| Aovs::new(self.image_width, self.image_height, pixel_aovs));
+        (image, aovs, stats)
     }
 
     /// Create a ray from the defocus lens in the camera center, and direct
@@ -176,25 +299,190 @@ impl Camera {
         let mut rng = rand::thread_rng();
         vec3::Vec3(rng.gen_range(-0.5..0.5), rng.gen_range(-0.5..0.5), 0.)
     }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        integrator::{bdpt::Bdpt, debug::Normals, path::LightSampling, spectral::Spectral},
+        make_empty_cornell_box,
+        material::Lambertian,
+        sphere::Sphere,
+    };
+    use std::sync::OnceLock;
+
+    /// Cornell box with only diffuse surfaces, so every light path can be
+    /// sampled from the lights
+    fn make_cornell_box() -> (HittableList, HittableList) {
+        let (mut world, lights) = make_empty_cornell_box();
+        let white = || Lambertian::new(Color::new(0.73, 0.73, 0.73));
+        world.push(Sphere::new(Vec3(190., 90., 190.), 90., white()));
+        world.push(Sphere::new(Vec3(370., 120., 370.), 120., white()));
+        (world, lights)
+    }
+
+    fn cornell_camera(samples_per_pixel: usize, integrator: impl Integrator + 'static) -> Camera {
+        let mut builder = Camera::builder();
+        builder.aspect_ratio = 1.;
+        builder.image_width = 8;
+        builder.samples_per_pixel = samples_per_pixel;
+        // Framed below the ceiling light, so the noise of partially covered
+        // pixels does not hide the noise of the light transport
+        builder.vfov = 30.;
+        builder.look_from = Vec3(278., 250., -800.);
+        builder.look_to = Vec3(278., 250., 0.);
+        builder.background = Box::new(Gradient::new(Color::black(), Color::black()));
+        builder.integrator = Box::new(integrator);
+        builder.build()
+    }
 
-    /// Retrieve the final color of a ray traversing through the world
-    fn ray_color(r: ray::Ray, depth: usize, world: &impl Hittable) -> Color {
-        if depth == 0 {
-            return Color::black();
+    fn path_tracer(light_sampling: LightSampling) -> PathTracer {
+        PathTracer {
+            light_sampling,
+            ..Default::default()
         }
-        match world.hit(&r, &(0.001..INFINITY)) {
-            Some(t) => match t.material.scatter(&r, &t) {
-                Some((attenuation, scattered)) => {
-                    attenuation * Camera::ray_color(scattered, depth - 1, world)
-                }
-                None => Color::black(),
-            },
-            None => {
-                // This is the background branch
-                let unit_dir = r.direction.normalize();
-                let a = 0.5 * (unit_dir.1 + 1.);
-                Color::new(1., 1., 1.) * (1. - a) + a * Color::new(0.6, 0.5, 1.0)
-            }
+    }
+
+    /// High sample count render every other render is compared against
+    fn reference() -> &'static Vec<Color> {
+        static REFERENCE: OnceLock<Vec<Color>> = OnceLock::new();
+        REFERENCE.get_or_init(|| {
+            let (world, lights) = make_cornell_box();
+            cornell_camera(2048, path_tracer(LightSampling::Mis))
+                .render_pixels(&world, &lights)
+                .0
+        })
+    }
+
+    fn luminance(c: &Color) -> f32 {
+        0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2]
+    }
+
+    /// Relative difference of the average brightness of two images
+    fn mean_error(pixels: &[Color], expected: &[Color]) -> f32 {
+        let mean = pixels.iter().map(luminance).sum::<f32>();
+        let expected = expected.iter().map(luminance).sum::<f32>();
+        ((mean - expected) / expected).abs()
+    }
+
+    /// Root mean square of the per pixel difference, as a measure of noise
+    fn rmse(pixels: &[Color], expected: &[Color]) -> f32 {
+        let sum = pixels
+            .iter()
+            .zip(expected)
+            .map(|(a, b)| (luminance(a) - luminance(b)).powi(2))
+            .sum::<f32>();
+        (sum / (pixels.len() as f32)).sqrt()
+    }
+
+    #[test]
+    fn cornell_mis_converges() {
+        let (world, lights) = make_cornell_box();
+        let mut camera = cornell_camera(256, path_tracer(LightSampling::Mis));
+        let (pixels, _) = camera.render_pixels(&world, &lights);
+
+        assert!(mean_error(&pixels, reference()) < 0.05);
+    }
+
+    #[test]
+    fn cornell_mixture_converges() {
+        let (world, lights) = make_cornell_box();
+        let mut camera = cornell_camera(256, path_tracer(LightSampling::Mixture));
+        let (pixels, _) = camera.render_pixels(&world, &lights);
+
+        assert!(mean_error(&pixels, reference()) < 0.05);
+    }
+
+    #[test]
+    fn cornell_spectral_converges() {
+        let (world, lights) = make_cornell_box();
+        let spectral = Spectral {
+            inner: Box::new(path_tracer(LightSampling::Mis)),
+        };
+        let mut camera = cornell_camera(512, spectral);
+        let (pixels, _) = camera.render_pixels(&world, &lights);
+
+        assert!(mean_error(&pixels, reference()) < 0.05);
+        // The colored walls keep their colors
+        let total: Color = pixels.iter().cloned().sum();
+        let expected: Color = reference().iter().cloned().sum();
+        for channel in 0..3 {
+            assert!((total[channel] / expected[channel] - 1.).abs() < 0.05);
         }
     }
+
+    #[test]
+    fn cornell_bdpt_converges() {
+        let (world, lights) = make_cornell_box();
+        let mut camera = cornell_camera(256, Bdpt { max_depth: 16 });
+        let (pixels, _) = camera.render_pixels(&world, &lights);
+
+        assert!(mean_error(&pixels, reference()) < 0.05);
+    }
+
+    #[test]
+    fn cornell_without_light_sampling_converges() {
+        // The ceiling light can only be found by chance
+        let (world, lights) = make_cornell_box();
+        let mut camera = cornell_camera(1024, path_tracer(LightSampling::None));
+        let (pixels, _) = camera.render_pixels(&world, &lights);
+
+        assert!(mean_error(&pixels, reference()) < 0.1);
+    }
+
+    #[test]
+    fn cornell_mis_less_noisy_than_bsdf_sampling() {
+        let (world, lights) = make_cornell_box();
+        let (with_lights, _) =
+            cornell_camera(64, path_tracer(LightSampling::Mis)).render_pixels(&world, &lights);
+        let (without_lights, _) =
+            cornell_camera(64, path_tracer(LightSampling::None)).render_pixels(&world, &lights);
+
+        assert!(rmse(&with_lights, reference()) < rmse(&without_lights, reference()));
+    }
+
+    #[test]
+    fn russian_roulette_unbiased() {
+        let (world, lights) = make_cornell_box();
+        let integrator = PathTracer {
+            roulette_depth: 0,
+            ..Default::default()
+        };
+        let (pixels, stats) = cornell_camera(256, integrator).render_pixels(&world, &lights);
+
+        assert!(mean_error(&pixels, reference()) < 0.05);
+
+        // Without roulette paths only leave through the open side of the box
+        let integrator = PathTracer {
+            roulette_depth: 50,
+            ..Default::default()
+        };
+        let (_, full_stats) = cornell_camera(256, integrator).render_pixels(&world, &lights);
+        assert!(stats.average_path_length() < full_stats.average_path_length());
+    }
+
+    #[test]
+    fn alpha_only_counts_traced_paths() {
+        let mut world = HittableList::default();
+        world.push(Sphere::new(
+            Vec3(0., 0., -2.),
+            0.5,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ));
+        let mut builder = Camera::builder();
+        builder.image_width = 16;
+        builder.samples_per_pixel = 4;
+        builder.integrator = Box::new(Normals);
+        builder.alpha = true;
+        let (image, _, stats) = builder
+            .build()
+            .render_samples(&world, &HittableList::default());
+
+        let coverage: f32 = image.alpha.unwrap().iter().sum();
+        assert!(coverage > 0. && coverage < image.pixels.len() as f32);
+        assert_eq!(stats.paths, (coverage * 4.).round() as usize);
+        // Every path that was traced hit the sphere
+        assert_eq!(stats.average_path_length(), 1.);
+    }
 }
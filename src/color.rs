@@ -1,12 +1,79 @@
 use crate::vec3;
 use std::{fmt, iter, ops};
 
-pub fn linear_to_gamma(linear: f32) -> f32 {
+/// Encodes a linear value with the piecewise sRGB transfer curve
+pub fn linear_to_srgb(linear: f32) -> f32 {
     match linear {
-        x if x > 0. => x.sqrt(),
-        _ => 0.,
+        x if x <= 0. => 0.,
+        x if x <= 0.0031308 => 12.92 * x,
+        x => 1.055 * x.powf(1. / 2.4) - 0.055,
     }
 }
+
+/// Decodes an sRGB encoded value, like the texels of an 8 bit image, back
+/// to linear
+pub fn srgb_to_linear(encoded: f32) -> f32 {
+    match encoded {
+        x if x <= 0. => 0.,
+        x if x <= 0.04045 => x / 12.92,
+        x => ((x + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+/// RGB spaces the renderer can work in. The colors of a scene are taken to
+/// be in the working space, so they are converted from it for display.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorSpace {
+    /// Rec. 709 primaries with a D65 white point, without the sRGB curve
+    #[default]
+    LinearSrgb,
+    /// The wider AP1 primaries of ACES with a D60 white point, in which
+    /// light bouncing between saturated surfaces behaves more like it does
+    /// with spectra
+    AcesCg,
+}
+
+/// Linear sRGB to ACEScg, including the Bradford adaptation from D65 to
+/// D60 so white stays white
+const SRGB_TO_ACESCG: [[f32; 3]; 3] = [
+    [0.613097, 0.339523, 0.047379],
+    [0.070194, 0.916354, 0.013452],
+    [0.020616, 0.109570, 0.869815],
+];
+const ACESCG_TO_SRGB: [[f32; 3]; 3] = [
+    [1.704859, -0.621715, -0.083299],
+    [-0.130078, 1.140734, -0.010560],
+    [-0.023964, -0.128975, 1.153013],
+];
+
+impl ColorSpace {
+    /// Converts a linear sRGB color, such as the color of the sky, into
+    /// this space
+    pub fn convert_from_srgb(&self, color: &Color) -> Color {
+        match self {
+            ColorSpace::LinearSrgb => color.clone(),
+            ColorSpace::AcesCg => color.transform(&SRGB_TO_ACESCG),
+        }
+    }
+
+    /// Converts a color of this space into linear sRGB, for display
+    pub fn convert_to_srgb(&self, color: &Color) -> Color {
+        match self {
+            ColorSpace::LinearSrgb => color.clone(),
+            ColorSpace::AcesCg => color.transform(&ACESCG_TO_SRGB),
+        }
+    }
+
+    /// CIE xy coordinates of the red, green and blue primaries and of the
+    /// white point, for files that can label their colors
+    pub fn chromaticities(&self) -> [f32; 8] {
+        match self {
+            ColorSpace::LinearSrgb => [0.64, 0.33, 0.3, 0.6, 0.15, 0.06, 0.3127, 0.329],
+            ColorSpace::AcesCg => [0.713, 0.293, 0.165, 0.83, 0.128, 0.044, 0.32168, 0.33767],
+        }
+    }
+}
+
 /// The Color newtype is a vec3 that represents a color.
 /// Invariant:
 ///     The fields of this struct must be a float between 0 and 1.
@@ -21,6 +88,22 @@ impl Color {
     pub fn black() -> Self {
         Color::new(0., 0., 0.)
     }
+
+    /// Largest of the three channels
+    pub fn max_component(&self) -> f32 {
+        self[0].max(self[1]).max(self[2])
+    }
+
+    /// Multiplies the color by a matrix given row by row
+    pub fn transform(&self, matrix: &[[f32; 3]; 3]) -> Color {
+        let row = |r: &[f32; 3]| r[0] * self[0] + r[1] * self[1] + r[2] * self[2];
+        Color::new(row(&matrix[0]), row(&matrix[1]), row(&matrix[2]))
+    }
+
+    /// Relative luminance, with the Rec. 709 weights of linear sRGB
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self[0] + 0.7152 * self[1] + 0.0722 * self[2]
+    }
 }
 
 impl From<vec3::Vec3> for Color {
@@ -53,9 +136,9 @@ fn clamp(legal_range: &ops::Range<f32>, val: f32) -> f32 {
 impl fmt::Display for Color {
     /// Color has a different byte representation in ppm than vec3.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let r = linear_to_gamma(self.0[0]);
-        let g = linear_to_gamma(self.0[1]);
-        let b = linear_to_gamma(self.0[2]);
+        let r = linear_to_srgb(self.0[0]);
+        let g = linear_to_srgb(self.0[1]);
+        let b = linear_to_srgb(self.0[2]);
 
         let legal_range = 0f32..0.999;
 
@@ -155,10 +238,38 @@ mod test {
     fn color_print_struct() {
         let input = Color::new(0.5, 0.25, 0.125);
 
-        let expected = format!("{} {} {}", 181, 127, 90);
+        let expected = format!("{} {} {}", 188, 137, 99);
         assert_eq!(expected, format!("{}", input));
     }
 
+    #[test]
+    fn srgb_curve() {
+        assert_eq!(linear_to_srgb(-1.), 0.);
+        assert!((linear_to_srgb(0.0031308) - 0.04045).abs() < 1e-5);
+        assert!((linear_to_srgb(0.5) - 0.7354).abs() < 1e-4);
+        assert!((linear_to_srgb(1.) - 1.).abs() < 1e-6);
+        for i in 0..=100 {
+            let x = i as f32 / 100.;
+            assert!((srgb_to_linear(linear_to_srgb(x)) - x).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn acescg_round_trip_keeps_white() {
+        let white = ColorSpace::AcesCg.convert_from_srgb(&Color::new(1., 1., 1.));
+        for channel in 0..3 {
+            assert!((white[channel] - 1.).abs() < 1e-3);
+        }
+        let red = Color::new(0.8, 0.1, 0.05);
+        let aces = ColorSpace::AcesCg.convert_from_srgb(&red);
+        // The same red is less saturated among the wider primaries
+        assert!(aces[0] / aces[1] < red[0] / red[1]);
+        let back = ColorSpace::AcesCg.convert_to_srgb(&aces);
+        for channel in 0..3 {
+            assert!((back[channel] - red[channel]).abs() < 1e-4);
+        }
+    }
+
     #[test]
     fn color_usize_index() {
         let input = Color::new(0.5, 0.25, 0.125);
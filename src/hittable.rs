@@ -1,5 +1,6 @@
 use crate::{material::Material, ray, vec3};
-use std::ops::Range;
+use rand::Rng;
+use std::{ops::Range, sync::Arc};
 
 pub struct HitRecord<'a> {
     pub p: vec3::Point3,
@@ -7,9 +8,33 @@ pub struct HitRecord<'a> {
     pub t: f32,
     pub front_face: bool,
     pub material: &'a dyn Material,
+    /// Order in which the object hit was pushed into its `HittableList`,
+    /// which stays the same from one run to the next
+    pub object_id: usize,
 }
 
 impl<'a> HitRecord<'a> {
+    /// Hit record of a point on a surface seen from outside, meaning against
+    /// `outward_normal`
+    pub fn from_outside(
+        p: vec3::Point3,
+        outward_normal: vec3::Vec3,
+        material: &'a dyn Material,
+    ) -> Self {
+        let r = ray::Ray::new(-outward_normal, p + outward_normal);
+        HitRecord::new(p, outward_normal, 1., &r, material)
+    }
+
+    /// Normal on the side the surface was built with, regardless of which
+    /// side it was hit from
+    pub fn outward_normal(&self) -> vec3::Vec3 {
+        if self.front_face {
+            self.normal
+        } else {
+            -self.normal
+        }
+    }
+
     pub fn new(
         p: vec3::Point3,
         normal: vec3::Vec3,
@@ -25,12 +50,61 @@ impl<'a> HitRecord<'a> {
             t,
             front_face,
             material,
+            object_id: 0,
         }
     }
 }
 
 pub trait Hittable: Sync {
-    fn hit(&self, r: &ray::Ray, ray_interval: &Range<f32>) -> Option<HitRecord>;
+    fn hit(&self, r: &ray::Ray, ray_interval: &Range<f32>) -> Option<HitRecord<'_>>;
+
+    /// Probability density, with respect to solid angle, of `random`
+    /// generating `direction` from `origin`. Only hittables that can be used
+    /// as lights need to implement this.
+    fn pdf_value(&self, _origin: &vec3::Point3, _direction: &vec3::Vec3) -> f32 {
+        0.
+    }
+
+    /// Random direction from `origin` towards a point on the hittable
+    fn random(&self, _origin: &vec3::Point3) -> vec3::Vec3 {
+        vec3::Vec3(1., 0., 0.)
+    }
+
+    /// Random point on the surface, returned as if it was hit from the
+    /// outside, together with the density of picking it with respect to
+    /// area. Used to start paths on lights.
+    fn sample_surface(&self) -> Option<(HitRecord<'_>, f32)> {
+        None
+    }
+
+    /// Probability density, with respect to area, of `sample_surface`
+    /// picking the point `direction` from `origin` reaches
+    fn surface_pdf_value(&self, _origin: &vec3::Point3, _direction: &vec3::Vec3) -> f32 {
+        0.
+    }
+}
+
+/// Lets the same object be placed in both the world and the light list
+impl<H: Hittable + Send + ?Sized> Hittable for Arc<H> {
+    fn hit(&self, r: &ray::Ray, ray_interval: &Range<f32>) -> Option<HitRecord<'_>> {
+        self.as_ref().hit(r, ray_interval)
+    }
+
+    fn pdf_value(&self, origin: &vec3::Point3, direction: &vec3::Vec3) -> f32 {
+        self.as_ref().pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &vec3::Point3) -> vec3::Vec3 {
+        self.as_ref().random(origin)
+    }
+
+    fn sample_surface(&self) -> Option<(HitRecord<'_>, f32)> {
+        self.as_ref().sample_surface()
+    }
+
+    fn surface_pdf_value(&self, origin: &vec3::Point3, direction: &vec3::Vec3) -> f32 {
+        self.as_ref().surface_pdf_value(origin, direction)
+    }
 }
 
 #[derive(Default)]
@@ -39,6 +113,7 @@ pub struct HittableList {
 }
 
 impl HittableList {
+    /// Adds an object, whose ID is the number of objects pushed before it
     pub fn push<H: Hittable + 'static>(&mut self, value: H) {
         // let rc_value: Rc<dyn Hittable> = rc::Rc::clone(value);
         let boxed_val: Box<dyn Hittable> = Box::new(value);
@@ -54,26 +129,59 @@ impl HittableList {
     pub fn len(&self) -> usize {
         self.objects.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
 }
 
 impl Hittable for HittableList {
     /// If the ray, r, hits a hittable, then return the hit record for the
     /// closest hittable
-    fn hit(&self, r: &ray::Ray, ray_interval: &Range<f32>) -> Option<HitRecord> {
+    fn hit(&self, r: &ray::Ray, ray_interval: &Range<f32>) -> Option<HitRecord<'_>> {
         let mut hit_record: Option<HitRecord> = None;
         let mut closest_so_far = ray_interval.clone();
-        for hit_obj_rc in self.objects.iter() {
+        for (object_id, hit_obj_rc) in self.objects.iter().enumerate() {
             let hit_obj = hit_obj_rc.as_ref();
             hit_record = match hit_obj.hit(r, &closest_so_far) {
                 Some(x) if x.t < closest_so_far.end => {
                     closest_so_far.end = x.t;
-                    Some(x)
+                    Some(HitRecord { object_id, ..x })
                 }
                 _ => continue,
             }
         }
         hit_record
     }
+
+    /// Each object is picked with equal probability, so the density is the
+    /// average of the densities of the objects
+    fn pdf_value(&self, origin: &vec3::Point3, direction: &vec3::Vec3) -> f32 {
+        let weight = 1. / (self.objects.len() as f32);
+        self.objects
+            .iter()
+            .map(|obj| weight * obj.pdf_value(origin, direction))
+            .sum()
+    }
+
+    fn random(&self, origin: &vec3::Point3) -> vec3::Vec3 {
+        let index = rand::thread_rng().gen_range(0..self.objects.len());
+        self.objects[index].random(origin)
+    }
+
+    fn sample_surface(&self) -> Option<(HitRecord<'_>, f32)> {
+        let index = rand::thread_rng().gen_range(0..self.objects.len());
+        let (rec, pdf) = self.objects[index].sample_surface()?;
+        Some((rec, pdf / (self.objects.len() as f32)))
+    }
+
+    fn surface_pdf_value(&self, origin: &vec3::Point3, direction: &vec3::Vec3) -> f32 {
+        let weight = 1. / (self.objects.len() as f32);
+        self.objects
+            .iter()
+            .map(|obj| weight * obj.surface_pdf_value(origin, direction))
+            .sum()
+    }
 }
 
 #[cfg(test)]
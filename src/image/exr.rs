@@ -0,0 +1,322 @@
+//! Single part scanline OpenEXR files, following "The OpenEXR File Layout"
+//! from the OpenEXR documentation.
+
+use crate::color::Color;
+use flate2::{write::ZlibEncoder, Compression as Level};
+use std::io::{self, Write};
+
+/// How each channel value is stored
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PixelType {
+    /// 16 bit float, enough for viewing and half the size
+    #[default]
+    Half,
+    /// 32 bit float
+    Float,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Compression {
+    None,
+    /// Lossless zlib compression of blocks of 16 scanlines
+    #[default]
+    Zip,
+}
+
+impl Compression {
+    fn scanlines_per_block(&self) -> usize {
+        match self {
+            Compression::None => 1,
+            Compression::Zip => 16,
+        }
+    }
+}
+
+/// A named channel, with values row by row from the top left corner
+pub struct Channel {
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+/// Splits pixels into channels named `R`, `G` and `B`, prefixed by `layer`
+/// and a dot unless it is empty
+pub fn rgb_channels(layer: &str, pixels: &[Color]) -> Vec<Channel> {
+    ["R", "G", "B"]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| Channel {
+            name: if layer.is_empty() {
+                name.to_string()
+            } else {
+                format!("{layer}.{name}")
+            },
+            values: pixels.iter().map(|pixel| pixel[i]).collect(),
+        })
+        .collect()
+}
+
+/// Writes the channels of a `width` by `height` image, whose colors have
+/// the given `chromaticities`: the CIE xy coordinates of the red, green and
+/// blue primaries followed by the white point
+pub fn write(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    channels: &[Channel],
+    chromaticities: &[f32; 8],
+    pixel_type: PixelType,
+    compression: Compression,
+) -> io::Result<()> {
+    // Readers expect channels sorted by name
+    let mut channels: Vec<&Channel> = channels.iter().collect();
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut header = Vec::new();
+    // Magic number, then version 2 with no flags, for a single part
+    // scanline file
+    header.extend(20000630i32.to_le_bytes());
+    header.extend(2i32.to_le_bytes());
+
+    let mut channel_list = Vec::new();
+    for channel in &channels {
+        channel_list.extend(channel.name.as_bytes());
+        channel_list.push(0);
+        let type_id: i32 = match pixel_type {
+            PixelType::Half => 1,
+            PixelType::Float => 2,
+        };
+        channel_list.extend(type_id.to_le_bytes());
+        // Not perceptually linear, three reserved bytes, no subsampling
+        channel_list.extend([0, 0, 0, 0]);
+        channel_list.extend(1i32.to_le_bytes());
+        channel_list.extend(1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+    attribute(&mut header, "channels", "chlist", &channel_list);
+    let chromaticities: Vec<u8> = chromaticities
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    attribute(
+        &mut header,
+        "chromaticities",
+        "chromaticities",
+        &chromaticities,
+    );
+
+    let compression_id: u8 = match compression {
+        Compression::None => 0,
+        Compression::Zip => 3,
+    };
+    attribute(&mut header, "compression", "compression", &[compression_id]);
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    // Increasing y
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    let block_height = compression.scanlines_per_block();
+    let blocks: Vec<Vec<u8>> = (0..height)
+        .step_by(block_height)
+        .map(|y| {
+            let rows = y..(y + block_height).min(height);
+            let mut raw = Vec::new();
+            for row in rows {
+                for channel in &channels {
+                    for value in &channel.values[row * width..(row + 1) * width] {
+                        match pixel_type {
+                            PixelType::Half => raw.extend(f32_to_half(*value).to_le_bytes()),
+                            PixelType::Float => raw.extend(value.to_le_bytes()),
+                        }
+                    }
+                }
+            }
+            let data = match compression {
+                Compression::None => raw,
+                Compression::Zip => zip(&raw),
+            };
+
+            let mut block = Vec::with_capacity(data.len() + 8);
+            block.extend((y as i32).to_le_bytes());
+            block.extend((data.len() as i32).to_le_bytes());
+            block.extend(data);
+            block
+        })
+        .collect();
+
+    // Table of where every block starts, from the start of the file
+    let mut offset = (header.len() + 8 * blocks.len()) as u64;
+    out.write_all(&header)?;
+    for block in &blocks {
+        out.write_all(&offset.to_le_bytes())?;
+        offset += block.len() as u64;
+    }
+    for block in &blocks {
+        out.write_all(block)?;
+    }
+    Ok(())
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend(name.as_bytes());
+    header.push(0);
+    header.extend(kind.as_bytes());
+    header.push(0);
+    header.extend((value.len() as i32).to_le_bytes());
+    header.extend(value);
+}
+
+/// Compresses a block the way OpenEXR does. The bytes are split into the
+/// even and the odd ones, stored as differences from the previous byte and
+/// then deflated. Blocks that would grow are stored as they are.
+fn zip(raw: &[u8]) -> Vec<u8> {
+    let mut reordered: Vec<u8> = Vec::with_capacity(raw.len());
+    reordered.extend(raw.iter().step_by(2));
+    reordered.extend(raw.iter().skip(1).step_by(2));
+    for i in (1..reordered.len()).rev() {
+        reordered[i] = reordered[i]
+            .wrapping_sub(reordered[i - 1])
+            .wrapping_add(128);
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Level::default());
+    let compressed = encoder
+        .write_all(&reordered)
+        .and_then(|_| encoder.finish())
+        .expect("writing to memory cannot fail");
+    if compressed.len() < raw.len() {
+        compressed
+    } else {
+        raw.to_vec()
+    }
+}
+
+/// Converts to a 16 bit float, rounding to the nearest even value
+pub fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        // Infinity stays infinity, and NaN stays NaN
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        // Too large, so infinity
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        if exponent < -10 {
+            // Too small even for a subnormal
+            return sign;
+        }
+        // Subnormal, with the implicit leading bit made explicit
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let half = mantissa >> shift;
+        let rest = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round = rest > halfway || (rest == halfway && half & 1 == 1);
+        return sign | (half + u32::from(round)) as u16;
+    }
+
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let rest = mantissa & 0x1fff;
+    let round = rest > 0x1000 || (rest == 0x1000 && half & 1 == 1);
+    // Rounding up may carry into the exponent, which is still correct
+    sign | (half + u32::from(round)) as u16
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::ColorSpace;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn half_conversion() {
+        assert_eq!(f32_to_half(0.), 0);
+        assert_eq!(f32_to_half(1.), 0x3c00);
+        assert_eq!(f32_to_half(-2.), 0xc000);
+        assert_eq!(f32_to_half(65504.), 0x7bff);
+        assert_eq!(f32_to_half(1e6), 0x7c00);
+        assert_eq!(f32_to_half(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_half(f32::NAN) & 0x7c00, 0x7c00);
+        // Smallest subnormal
+        assert_eq!(f32_to_half(5.96e-8), 1);
+        // 1 + 2^-11 is halfway between two halves, and rounds to even
+        assert_eq!(f32_to_half(1. + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f32_to_half(1. + 3. * 2f32.powi(-11)), 0x3c02);
+    }
+
+    #[test]
+    fn zip_round_trip() {
+        let raw: Vec<u8> = (0..1000).map(|i| (i / 7) as u8).collect();
+        let compressed = zip(&raw);
+        assert!(compressed.len() < raw.len());
+
+        let mut reordered = Vec::new();
+        ZlibDecoder::new(&compressed[..])
+            .read_to_end(&mut reordered)
+            .unwrap();
+        for i in 1..reordered.len() {
+            reordered[i] = reordered[i]
+                .wrapping_add(reordered[i - 1])
+                .wrapping_sub(128);
+        }
+        let (even, odd) = reordered.split_at(raw.len().div_ceil(2));
+        let restored: Vec<u8> = (0..raw.len())
+            .map(|i| if i % 2 == 0 { even[i / 2] } else { odd[i / 2] })
+            .collect();
+        assert_eq!(restored, raw);
+    }
+
+    #[test]
+    fn uncompressed_layout() {
+        let channels = rgb_channels("", &[Color::new(1., 2., 3.), Color::new(4., 5., 6.)]);
+        let mut out = Vec::new();
+        write(
+            &mut out,
+            1,
+            2,
+            &channels,
+            &ColorSpace::LinearSrgb.chromaticities(),
+            PixelType::Float,
+            Compression::None,
+        )
+        .unwrap();
+
+        assert_eq!(&out[..4], &[0x76, 0x2f, 0x31, 0x01]);
+        // Two one-line blocks, each with the y coordinate, the size and
+        // the B, G and R values
+        let block_size = 4 + 4 + 3 * 4;
+        let first = out.len() - 2 * block_size;
+        let offset = u64::from_le_bytes(out[first - 16..first - 8].try_into().unwrap());
+        assert_eq!(offset as usize, first);
+        let value = |at: usize| f32::from_le_bytes(out[at..at + 4].try_into().unwrap());
+        assert_eq!(value(first + 8), 3.);
+        assert_eq!(value(first + 16), 1.);
+        assert_eq!(value(first + block_size + 8), 6.);
+    }
+}
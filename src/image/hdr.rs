@@ -0,0 +1,302 @@
+//! Radiance HDR files, storing each pixel as RGBE: three 8 bit mantissas
+//! sharing an 8 bit exponent. Scanlines are written with the run length
+//! encoding Radiance itself uses, and read either encoded or flat.
+
+use super::Image;
+use crate::color::Color;
+use std::io::{self, BufRead, Write};
+
+/// Scanlines outside of these widths can not be run length encoded
+const ENCODED_WIDTHS: std::ops::Range<usize> = 8..0x8000;
+
+/// Longest run of repeated bytes, and longest literal chunk
+const MAX_RUN: usize = 127;
+const MAX_LITERAL: usize = 128;
+
+pub fn write(out: &mut impl Write, image: &Image) -> io::Result<()> {
+    write!(
+        out,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        image.height, image.width
+    )?;
+    for row in image.pixels.chunks(image.width) {
+        let rgbe: Vec<[u8; 4]> = row.iter().map(to_rgbe).collect();
+        if ENCODED_WIDTHS.contains(&image.width) {
+            write_encoded(out, &rgbe)?;
+        } else {
+            for pixel in &rgbe {
+                out.write_all(pixel)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes a scanline as its four components one after the other, each
+/// split into runs of a repeated byte and chunks of literal bytes
+fn write_encoded(out: &mut impl Write, rgbe: &[[u8; 4]]) -> io::Result<()> {
+    let width = rgbe.len();
+    out.write_all(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8])?;
+    for component in 0..4 {
+        let bytes: Vec<u8> = rgbe.iter().map(|pixel| pixel[component]).collect();
+        let mut i = 0;
+        while i < width {
+            let run = bytes[i..]
+                .iter()
+                .take(MAX_RUN)
+                .take_while(|b| **b == bytes[i])
+                .count();
+            // Short runs cost as much as the literal bytes
+            if run >= 4 {
+                out.write_all(&[128 + run as u8, bytes[i]])?;
+                i += run;
+                continue;
+            }
+            // Literals stop where a run worth encoding starts
+            let mut end = i;
+            while end < width && end - i < MAX_LITERAL {
+                if end + 4 <= width && bytes[end..end + 4].iter().all(|b| *b == bytes[end]) {
+                    break;
+                }
+                end += 1;
+            }
+            out.write_all(&[(end - i) as u8])?;
+            out.write_all(&bytes[i..end])?;
+            i = end;
+        }
+    }
+    Ok(())
+}
+
+/// Reads an image stored with its rows from the top and its columns from
+/// the left, which is how every common tool writes them
+pub fn read(input: &mut impl BufRead) -> io::Result<Image> {
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid("missing the #? signature"));
+    }
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Err(invalid("header does not end"));
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid(&format!("unsupported format {format}")));
+            }
+        }
+    }
+
+    line.clear();
+    input.read_line(&mut line)?;
+    let (height, width): (usize, usize) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (
+            height.parse().map_err(|_| invalid("bad height"))?,
+            width.parse().map_err(|_| invalid("bad width"))?,
+        ),
+        _ => return Err(invalid(&format!("unsupported orientation {}", line.trim()))),
+    };
+    if width == 0 || height == 0 {
+        return Err(invalid("image is empty"));
+    }
+
+    // The header is not to be trusted with allocating the pixels
+    let mut pixels = Vec::new();
+    width
+        .checked_mul(height)
+        .and_then(|size| pixels.try_reserve_exact(size).ok())
+        .ok_or_else(|| invalid("image is too large"))?;
+    let mut rgbe = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        read_scanline(input, &mut rgbe)?;
+        pixels.extend(rgbe.iter().map(from_rgbe));
+    }
+    Ok(Image {
+        width,
+        height,
+        pixels,
+        alpha: None,
+    })
+}
+
+fn read_scanline(input: &mut impl BufRead, rgbe: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = rgbe.len();
+    let mut start = [0u8; 4];
+    input.read_exact(&mut start)?;
+    let encoded =
+        start[0] == 2 && start[1] == 2 && start[2] & 0x80 == 0 && ENCODED_WIDTHS.contains(&width);
+    if !encoded {
+        // Flat pixels, and the first one was already read
+        rgbe[0] = start;
+        for pixel in &mut rgbe[1..] {
+            input.read_exact(pixel)?;
+        }
+        return Ok(());
+    }
+    if ((start[2] as usize) << 8 | start[3] as usize) != width {
+        return Err(invalid("scanline width does not match the image"));
+    }
+
+    for component in 0..4 {
+        let mut i = 0;
+        while i < width {
+            let mut count = [0u8; 1];
+            input.read_exact(&mut count)?;
+            if count[0] > 128 {
+                let run = (count[0] - 128) as usize;
+                if i + run > width {
+                    return Err(invalid("run overflows the scanline"));
+                }
+                let mut value = [0u8; 1];
+                input.read_exact(&mut value)?;
+                for pixel in &mut rgbe[i..i + run] {
+                    pixel[component] = value[0];
+                }
+                i += run;
+            } else {
+                let literal = count[0] as usize;
+                if literal == 0 || i + literal > width {
+                    return Err(invalid("bad literal length"));
+                }
+                let mut bytes = vec![0u8; literal];
+                input.read_exact(&mut bytes)?;
+                for (pixel, byte) in rgbe[i..i + literal].iter_mut().zip(bytes) {
+                    pixel[component] = byte;
+                }
+                i += literal;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Scales the channels so the largest has a mantissa in `0.5..1`, which
+/// keeps the most precision for the brightest channel
+fn to_rgbe(color: &Color) -> [u8; 4] {
+    let channels = [color[0], color[1], color[2]].map(|c| if c > 0. { c } else { 0. });
+    let max = channels[0].max(channels[1]).max(channels[2]);
+    if max < 1e-32 {
+        return [0; 4];
+    }
+    let mut exponent = max.log2().floor() as i32 + 1;
+    // Rounding in log2 can leave the mantissa just outside of 0.5..1
+    if max / 2f32.powi(exponent) >= 1. {
+        exponent += 1;
+    } else if max / 2f32.powi(exponent) < 0.5 {
+        exponent -= 1;
+    }
+    let exponent = exponent.clamp(-128, 127);
+    let scale = 256. / 2f32.powi(exponent);
+    let [r, g, b] = channels.map(|c| (c * scale).min(255.) as u8);
+    [r, g, b, (exponent + 128) as u8]
+}
+
+/// Decodes to the middle of the range each mantissa stands for
+fn from_rgbe(rgbe: &[u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::black();
+    }
+    let scale = 2f32.powi(rgbe[3] as i32 - 128 - 8);
+    let channel = |i: usize| (rgbe[i] as f32 + 0.5) * scale;
+    Color::new(channel(0), channel(1), channel(2))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn gradient(width: usize, height: usize) -> Image {
+        let pixels = (0..width * height)
+            .map(|i| {
+                let x = (i % width) as f32;
+                Color::new(x * 10., 0.25, 1. / (1. + i as f32))
+            })
+            .collect();
+        Image {
+            width,
+            height,
+            pixels,
+            alpha: None,
+        }
+    }
+
+    fn round_trip(image: &Image) -> Image {
+        let mut file = Vec::new();
+        write(&mut file, image).unwrap();
+        read(&mut file.as_slice()).unwrap()
+    }
+
+    fn assert_close(a: &Image, b: &Image) {
+        assert_eq!((a.width, a.height), (b.width, b.height));
+        for (a, b) in a.pixels.iter().zip(&b.pixels) {
+            let max = a[0].max(a[1]).max(a[2]);
+            for channel in 0..3 {
+                // Every channel shares the precision of the brightest one
+                assert!((a[channel] - b[channel]).abs() <= max / 128., "{a:?} {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn encoded_round_trip() {
+        let image = gradient(40, 3);
+        assert_close(&image, &round_trip(&image));
+    }
+
+    #[test]
+    fn flat_round_trip() {
+        // Too narrow to encode
+        let image = gradient(5, 2);
+        assert_close(&image, &round_trip(&image));
+    }
+
+    #[test]
+    fn runs_are_compressed() {
+        let image = Image {
+            width: 300,
+            height: 1,
+            pixels: vec![Color::new(1., 0.5, 0.25); 300],
+            alpha: None,
+        };
+        let mut file = Vec::new();
+        write(&mut file, &image).unwrap();
+        // Four components of three runs each, after the header
+        let header = "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 300\n".len();
+        assert_eq!(file.len(), header + 4 + 4 * 3 * 2);
+        assert_close(&image, &read(&mut file.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn exact_powers_of_two() {
+        for value in [0.5, 1., 2., 1024.] {
+            let rgbe = to_rgbe(&Color::new(value, 0., 0.));
+            assert_eq!(rgbe[0], 128);
+            let decoded = from_rgbe(&rgbe)[0];
+            assert!((decoded - value).abs() <= value / 256.);
+        }
+        assert_eq!(to_rgbe(&Color::black()), [0; 4]);
+    }
+
+    #[test]
+    fn rejects_other_orientations() {
+        let file = b"#?RADIANCE\n\n+Y 1 +X 1\n\0\0\0\0";
+        assert!(read(&mut file.as_slice()).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_sizes() {
+        for size in ["-Y 1 +X 0", "-Y 0 +X 1", "-Y 4000000000 +X 4000000000"] {
+            let file = format!("#?RADIANCE\n\n{size}\n\0\0\0\0");
+            assert!(read(&mut file.as_bytes()).is_err(), "{size}");
+        }
+    }
+}
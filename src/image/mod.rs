@@ -0,0 +1,20 @@
+//! Readers and writers for the image formats renders can be saved in
+
+use crate::color::Color;
+
+pub mod exr;
+pub mod hdr;
+pub mod pfm;
+pub mod png;
+pub mod ppm;
+
+/// Linear pixels, row by row from the top left corner
+#[derive(Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+    /// Coverage of each pixel, when the background was left out. The
+    /// pixels are then premultiplied by it.
+    pub alpha: Option<Vec<f32>>,
+}
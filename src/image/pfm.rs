@@ -0,0 +1,57 @@
+//! Portable float map, the floating point sibling of PPM. Pixels are stored
+//! as little endian floats, from the bottom row up.
+
+use crate::color::Color;
+use std::io::{self, Write};
+
+/// Writes three channel pixels given row by row from the top left corner
+pub fn write(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+) -> io::Result<()> {
+    write!(out, "PF\n{width} {height}\n-1.0\n")?;
+    for row in pixels.chunks(width).rev() {
+        for pixel in row {
+            for channel in 0..3 {
+                out.write_all(&pixel[channel].to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes single channel values given row by row from the top left corner
+pub fn write_gray(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    values: &[f32],
+) -> io::Result<()> {
+    write!(out, "Pf\n{width} {height}\n-1.0\n")?;
+    for row in values.chunks(width).rev() {
+        for value in row {
+            out.write_all(&value.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bottom_row_first() {
+        let pixels = [Color::new(1., 2., 3.), Color::new(4., 5., 6.)];
+        let mut out = Vec::new();
+        write(&mut out, 1, 2, &pixels).unwrap();
+
+        let header = b"PF\n1 2\n-1.0\n";
+        assert_eq!(&out[..header.len()], header);
+        let first = f32::from_le_bytes(out[header.len()..header.len() + 4].try_into().unwrap());
+        assert_eq!(first, 4.);
+        assert_eq!(out.len(), header.len() + 2 * 3 * 4);
+    }
+}
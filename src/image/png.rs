@@ -0,0 +1,249 @@
+//! Portable Network Graphics, with sRGB encoded colors of 8 or 16 bits per
+//! channel and an alpha channel when the image has one
+
+use super::Image;
+use crate::color::linear_to_srgb;
+use flate2::{write::ZlibEncoder, Compression, Crc};
+use std::io::{self, Write};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BitDepth {
+    #[default]
+    Eight,
+    Sixteen,
+}
+
+impl BitDepth {
+    fn bytes(&self) -> usize {
+        match self {
+            BitDepth::Eight => 1,
+            BitDepth::Sixteen => 2,
+        }
+    }
+}
+
+pub fn write(out: &mut impl Write, image: &Image, bit_depth: BitDepth) -> io::Result<()> {
+    let channels = if image.alpha.is_some() { 4 } else { 3 };
+    let pixel_bytes = channels * bit_depth.bytes();
+
+    out.write_all(&SIGNATURE)?;
+
+    let mut header = Vec::new();
+    header.extend((image.width as u32).to_be_bytes());
+    header.extend((image.height as u32).to_be_bytes());
+    // Bit depth, truecolor with or without alpha, then the only defined
+    // compression, filter and interlace methods
+    let color_type = if image.alpha.is_some() { 6 } else { 2 };
+    header.extend([8 * bit_depth.bytes() as u8, color_type, 0, 0, 0]);
+    write_chunk(out, b"IHDR", &header)?;
+    // Perceptual rendering intent, telling viewers the colors are sRGB
+    write_chunk(out, b"sRGB", &[0])?;
+
+    let mut data = ZlibEncoder::new(Vec::new(), Compression::default());
+    let mut previous = vec![0u8; image.width * pixel_bytes];
+    for (y, row) in image.pixels.chunks(image.width).enumerate() {
+        let mut samples = Vec::with_capacity(row.len() * channels);
+        for (x, pixel) in row.iter().enumerate() {
+            let alpha = image.alpha.as_ref().map(|alpha| alpha[y * image.width + x]);
+            // PNG alpha is not premultiplied
+            let scale = match alpha {
+                Some(alpha) if alpha > 0. => 1. / alpha,
+                Some(_) => 0.,
+                None => 1.,
+            };
+            for channel in 0..3 {
+                samples.push(linear_to_srgb(pixel[channel] * scale));
+            }
+            samples.extend(alpha);
+        }
+
+        let mut bytes = Vec::with_capacity(previous.len());
+        for sample in samples {
+            let sample = sample.clamp(0., 1.);
+            match bit_depth {
+                BitDepth::Eight => bytes.push((sample * 255.).round() as u8),
+                BitDepth::Sixteen => bytes.extend(((sample * 65535.).round() as u16).to_be_bytes()),
+            }
+        }
+        let (filter, filtered) = filter_row(&bytes, &previous, pixel_bytes);
+        data.write_all(&[filter])?;
+        data.write_all(&filtered)?;
+        previous = bytes;
+    }
+    write_chunk(out, b"IDAT", &data.finish()?)?;
+    write_chunk(out, b"IEND", &[])
+}
+
+fn write_chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    // The checksum covers the type as well as the data
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    out.write_all(&crc.sum().to_be_bytes())
+}
+
+/// Applies each of the five filters to the row, keeping the one whose bytes
+/// are closest to zero as that tends to compress best
+fn filter_row(row: &[u8], previous: &[u8], pixel_bytes: usize) -> (u8, Vec<u8>) {
+    (0..5u8)
+        .map(|filter| {
+            let filtered: Vec<u8> = (0..row.len())
+                .map(|i| {
+                    let left = if i >= pixel_bytes {
+                        row[i - pixel_bytes]
+                    } else {
+                        0
+                    };
+                    let up = previous[i];
+                    let up_left = if i >= pixel_bytes {
+                        previous[i - pixel_bytes]
+                    } else {
+                        0
+                    };
+                    let prediction = match filter {
+                        0 => 0,
+                        1 => left,
+                        2 => up,
+                        3 => ((left as u16 + up as u16) / 2) as u8,
+                        _ => paeth(left, up, up_left),
+                    };
+                    row[i].wrapping_sub(prediction)
+                })
+                .collect();
+            (filter, filtered)
+        })
+        .min_by_key(|(_, filtered)| {
+            filtered
+                .iter()
+                .map(|b| (*b as i8).unsigned_abs() as u32)
+                .sum::<u32>()
+        })
+        .unwrap()
+}
+
+/// Whichever neighbour is closest to `left + up - up_left`
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance = |b: u8| (estimate - b as i16).abs();
+    if distance(left) <= distance(up) && distance(left) <= distance(up_left) {
+        left
+    } else if distance(up) <= distance(up_left) {
+        up
+    } else {
+        up_left
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    /// Splits a file into its chunks, checking every checksum
+    fn chunks(file: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(file[..8], SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut rest = &file[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let kind: [u8; 4] = rest[4..8].try_into().unwrap();
+            let data = rest[8..8 + length].to_vec();
+            let mut crc = Crc::new();
+            crc.update(&rest[4..8 + length]);
+            let expected = u32::from_be_bytes(rest[8 + length..12 + length].try_into().unwrap());
+            assert_eq!(crc.sum(), expected);
+            chunks.push((kind, data));
+            rest = &rest[12 + length..];
+        }
+        chunks
+    }
+
+    /// Inflates and unfilters the image data into rows of bytes
+    fn decode(idat: &[u8], width: usize, pixel_bytes: usize) -> Vec<Vec<u8>> {
+        let mut data = Vec::new();
+        ZlibDecoder::new(idat).read_to_end(&mut data).unwrap();
+        let mut rows: Vec<Vec<u8>> = Vec::new();
+        for line in data.chunks(1 + width * pixel_bytes) {
+            let previous = rows.last().cloned().unwrap_or(vec![0; width * pixel_bytes]);
+            let mut row: Vec<u8> = Vec::new();
+            for (i, byte) in line[1..].iter().enumerate() {
+                let left = if i >= pixel_bytes {
+                    row[i - pixel_bytes]
+                } else {
+                    0
+                };
+                let up_left = if i >= pixel_bytes {
+                    previous[i - pixel_bytes]
+                } else {
+                    0
+                };
+                let prediction = match line[0] {
+                    0 => 0,
+                    1 => left,
+                    2 => previous[i],
+                    3 => ((left as u16 + previous[i] as u16) / 2) as u8,
+                    _ => paeth(left, previous[i], up_left),
+                };
+                row.push(byte.wrapping_add(prediction));
+            }
+            rows.push(row);
+        }
+        rows
+    }
+
+    fn image(alpha: Option<Vec<f32>>) -> Image {
+        Image {
+            width: 3,
+            height: 2,
+            pixels: vec![
+                Color::new(0., 0.5, 1.),
+                Color::new(2., 0.001, 0.2),
+                Color::new(0.25, 0.25, 0.25),
+                Color::new(0.1, 0.2, 0.3),
+                Color::new(0.4, 0.5, 0.6),
+                Color::black(),
+            ],
+            alpha,
+        }
+    }
+
+    #[test]
+    fn eight_bit_rgb() {
+        let mut file = Vec::new();
+        write(&mut file, &image(None), BitDepth::Eight).unwrap();
+        let chunks = chunks(&file);
+        let kinds: Vec<_> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"sRGB", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 3, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+
+        let rows = decode(&chunks[2].1, 3, 3);
+        // sRGB encoded and clamped
+        assert_eq!(rows[0][..6], [0, 188, 255, 255, 3, 124]);
+        assert_eq!(rows[1][6..], [0, 0, 0]);
+    }
+
+    #[test]
+    fn sixteen_bit_alpha_is_unpremultiplied() {
+        let mut file = Vec::new();
+        let alpha = vec![1., 1., 0.5, 1., 1., 0.];
+        write(&mut file, &image(Some(alpha)), BitDepth::Sixteen).unwrap();
+        let chunks = chunks(&file);
+        assert_eq!(chunks[0].1[8..10], [16, 6]);
+
+        let rows = decode(&chunks[2].1, 3, 8);
+        let sample = |row: &[u8], i: usize| u16::from_be_bytes([row[2 * i], row[2 * i + 1]]);
+        // A quarter of a half covered pixel is half of its color
+        let half = (linear_to_srgb(0.5) * 65535.).round() as u16;
+        assert_eq!(sample(&rows[0], 8), half);
+        assert_eq!(sample(&rows[0], 11), 32768);
+        assert_eq!(sample(&rows[1], 11), 0);
+        assert_eq!(sample(&rows[0], 2), 65535);
+    }
+}
@@ -0,0 +1,154 @@
+//! PPM files. Renders are written as plain text, clamped to 8 bits per
+//! channel, while both the plain and the binary variants can be read.
+
+use super::Image;
+use crate::color::{srgb_to_linear, Color};
+use std::io::{self, Read, Write};
+
+pub fn write(out: &mut impl Write, image: &Image) -> io::Result<()> {
+    writeln!(out, "P3\n{} {}\n255", image.width, image.height)?;
+    for pixel in &image.pixels {
+        writeln!(out, "{}", pixel)?;
+    }
+    Ok(())
+}
+
+/// Reads a plain (`P3`) or binary (`P6`) file. Its values are taken to be
+/// sRGB encoded like those of other 8 bit images, and are decoded to linear.
+pub fn read(input: &mut impl Read) -> io::Result<Image> {
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    let mut position = 0;
+
+    let magic = token(&bytes, &mut position).to_vec();
+    let width = number(&bytes, &mut position)? as usize;
+    let height = number(&bytes, &mut position)? as usize;
+    let max = number(&bytes, &mut position)?;
+    if max == 0 || max > 0xffff {
+        return Err(invalid(&format!("unsupported maximum value {max}")));
+    }
+
+    let count = width
+        .checked_mul(height)
+        .and_then(|size| size.checked_mul(3))
+        .filter(|count| *count > 0)
+        .ok_or_else(|| invalid("unsupported size"))?;
+    let values: Vec<u32> = match magic.as_slice() {
+        b"P3" => (0..count)
+            .map(|_| number(&bytes, &mut position))
+            .collect::<io::Result<_>>()?,
+        b"P6" => {
+            // A single whitespace byte separates the header from the samples
+            position += 1;
+            let size: usize = if max < 0x100 { 1 } else { 2 };
+            bytes
+                .get(position..position.saturating_add(size.saturating_mul(count)))
+                .ok_or_else(|| invalid("pixels are missing"))?
+                .chunks(size)
+                .map(|sample| sample.iter().fold(0, |v, b| v << 8 | *b as u32))
+                .collect()
+        }
+        _ => return Err(invalid("missing the P3 or P6 signature")),
+    };
+
+    let decode = |value: u32| srgb_to_linear(value.min(max) as f32 / max as f32);
+    Ok(Image {
+        width,
+        height,
+        pixels: values
+            .chunks(3)
+            .map(|rgb| Color::new(decode(rgb[0]), decode(rgb[1]), decode(rgb[2])))
+            .collect(),
+        alpha: None,
+    })
+}
+
+/// Next run of bytes up to a whitespace, skipping whitespace and comments
+/// before it
+fn token<'a>(bytes: &'a [u8], position: &mut usize) -> &'a [u8] {
+    loop {
+        match bytes.get(*position) {
+            Some(b'#') => {
+                while bytes.get(*position).is_some_and(|b| *b != b'\n') {
+                    *position += 1;
+                }
+            }
+            Some(b) if b.is_ascii_whitespace() => *position += 1,
+            _ => break,
+        }
+    }
+    let start = *position;
+    while bytes
+        .get(*position)
+        .is_some_and(|b| !b.is_ascii_whitespace())
+    {
+        *position += 1;
+    }
+    &bytes[start..*position]
+}
+
+fn number(bytes: &[u8], position: &mut usize) -> io::Result<u32> {
+    let token = token(bytes, position);
+    std::str::from_utf8(token)
+        .ok()
+        .and_then(|token| token.parse().ok())
+        .ok_or_else(|| invalid("expected a number"))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn plain_round_trip() {
+        let image = Image {
+            width: 2,
+            height: 1,
+            pixels: vec![Color::new(0.5, 0.2, 0.), Color::new(0.01, 0.9, 0.3)],
+            alpha: None,
+        };
+        let mut file = Vec::new();
+        write(&mut file, &image).unwrap();
+        let read = read(&mut file.as_slice()).unwrap();
+
+        assert_eq!((read.width, read.height), (2, 1));
+        for (a, b) in read.pixels.iter().zip(&image.pixels) {
+            for channel in 0..3 {
+                // Within a step of 8 bits, which is finest near black
+                assert!((a[channel] - b[channel]).abs() < 0.01, "{a:?} {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn binary_values_are_decoded() {
+        let mut file = b"P6\n# a comment\n2 1 255\n".to_vec();
+        file.extend([255, 0, 188, 0, 0, 0]);
+        let image = read(&mut file.as_slice()).unwrap();
+
+        assert_eq!(image.pixels[0][0], 1.);
+        assert_eq!(image.pixels[0][1], 0.);
+        // The encoding spends more values on dark tones, so half the
+        // light is well above half of 255
+        assert!(
+            (image.pixels[0][2] - 0.5).abs() < 0.01,
+            "{:?}",
+            image.pixels
+        );
+        assert_eq!(image.pixels[1], Color::black());
+
+        assert!(read(&mut b"P6\n2 1 255\n\0\0\0".as_slice()).is_err());
+    }
+
+    #[test]
+    fn bad_sizes_are_rejected() {
+        for size in ["0 1", "4000000000 4000000000"] {
+            let file = format!("P6\n{size} 255\n\0\0\0");
+            assert!(read(&mut file.as_bytes()).is_err(), "{size}");
+        }
+    }
+}
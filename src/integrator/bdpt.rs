@@ -0,0 +1,477 @@
+use super::{Integrator, Scene};
+use crate::{
+    color::Color,
+    hittable::{HitRecord, Hittable},
+    material::ScatterRecord,
+    pdf::{CosinePdf, HittablePdf, Pdf},
+    ray::Ray,
+    vec3::Point3,
+};
+use std::f32::consts::PI;
+
+/// Bidirectional path tracer. Every camera ray starts a camera subpath, and
+/// a light subpath is started from a random point on the lights. Each
+/// vertex of one is connected to each vertex of the other, and all the ways
+/// of building the same path are combined with the power heuristic.
+///
+/// Light subpaths are never connected to the camera itself, so caustics
+/// seen directly by the camera are still only found by the camera subpath.
+/// Light arriving from the background is also only found by the camera
+/// subpath, and the lights are expected to emit like `DiffuseLight`.
+pub struct Bdpt {
+    /// Maximum number of surfaces a full path may hit
+    pub max_depth: usize,
+}
+
+impl Default for Bdpt {
+    fn default() -> Self {
+        Bdpt { max_depth: 8 }
+    }
+}
+
+/// A point along a subpath
+struct Vertex<'a> {
+    rec: HitRecord<'a>,
+    /// Product of the weights of every bounce before reaching the vertex.
+    /// On light subpaths this includes the emitted light.
+    beta: Color,
+    /// The material scatters into a single direction, so the vertex cannot
+    /// be connected to
+    delta: bool,
+}
+
+impl Integrator for Bdpt {
+    fn ray_color_with_hit<'a>(
+        &self,
+        r: Ray,
+        hit: Option<HitRecord<'a>>,
+        scene: &Scene<'a>,
+    ) -> (Color, usize) {
+        let camera = r.origin;
+        let (camera_path, mut color) = self.camera_subpath(r, hit, scene);
+        let light_path = self.light_subpath(scene);
+
+        for (t, vertex) in camera_path.iter().enumerate() {
+            // Number of segments between the camera and this vertex
+            let depth = t + 1;
+
+            // The camera subpath reached a light on its own
+            let emitted = vertex
+                .rec
+                .material
+                .emitted(&incoming(&camera, &camera_path, t), &vertex.rec);
+            if emitted.max_component() > 0. {
+                let path: Vec<_> = camera_path[..=t]
+                    .iter()
+                    .map(|v| (&v.rec, v.delta))
+                    .collect();
+                let weight = mis_weight(&camera, &path, 0, scene);
+                color += weight * (vertex.beta.clone() * emitted);
+            }
+
+            if vertex.delta || depth >= self.max_depth {
+                continue;
+            }
+            let r_in = incoming(&camera, &camera_path, t);
+
+            // Connect to a new point sampled on the lights
+            if let Some((contribution, light_rec)) = self.connect_to_light(&r_in, vertex, scene) {
+                let mut path: Vec<_> = camera_path[..=t]
+                    .iter()
+                    .map(|v| (&v.rec, v.delta))
+                    .collect();
+                path.push((&light_rec, false));
+                let weight = mis_weight(&camera, &path, 1, scene);
+                color += weight * contribution;
+            }
+
+            // Connect to every scattering vertex of the light subpath
+            for (s, light_vertex) in light_path.iter().enumerate().skip(1) {
+                if light_vertex.delta || depth + s + 1 > self.max_depth {
+                    continue;
+                }
+                let contribution = connect(&r_in, vertex, &light_path, s, scene);
+                if contribution.max_component() <= 0. {
+                    continue;
+                }
+                let mut path: Vec<_> = camera_path[..=t]
+                    .iter()
+                    .map(|v| (&v.rec, v.delta))
+                    .collect();
+                path.extend(light_path[..=s].iter().rev().map(|v| (&v.rec, v.delta)));
+                let weight = mis_weight(&camera, &path, s + 1, scene);
+                color += weight * contribution;
+            }
+        }
+
+        (color, camera_path.len())
+    }
+}
+
+impl Bdpt {
+    /// Traces the camera ray, which first hits `hit`, through the scene.
+    /// Light from the background can only be found this way, so it is added
+    /// in directly.
+    fn camera_subpath<'a>(
+        &self,
+        r: Ray,
+        hit: Option<HitRecord<'a>>,
+        scene: &Scene<'a>,
+    ) -> (Vec<Vertex<'a>>, Color) {
+        let mut path = Vec::new();
+        let mut r = r;
+        let mut beta = Color::new(1., 1., 1.);
+        let mut first_hit = Some(hit);
+
+        while path.len() < self.max_depth {
+            let hit = first_hit
+                .take()
+                .unwrap_or_else(|| scene.world.hit(&r, &(0.001..f32::INFINITY)));
+            let Some(rec) = hit else {
+                let mut background = scene.background.color(&r.direction);
+                if let Some(sun) = scene.background.sun() {
+                    background += sun.radiance(&r.direction);
+                }
+                return (path, beta * background);
+            };
+            let Some((weight, scattered, delta)) = scatter(&r, &rec) else {
+                path.push(Vertex {
+                    rec,
+                    beta,
+                    delta: false,
+                });
+                break;
+            };
+            path.push(Vertex {
+                rec,
+                beta: beta.clone(),
+                delta,
+            });
+            beta = beta * weight;
+            r = scattered;
+        }
+
+        (path, Color::black())
+    }
+
+    /// Starts a path on a random point of the lights and follows it through
+    /// the scene. The first vertex is the point on the light.
+    fn light_subpath<'a>(&self, scene: &Scene<'a>) -> Vec<Vertex<'a>> {
+        let mut path = Vec::new();
+        if scene.lights.is_empty() {
+            return path;
+        }
+        let Some((rec, pdf_pos)) = scene.lights.sample_surface() else {
+            return path;
+        };
+
+        // Lights emit with a cosine distribution around their normal
+        let emission = CosinePdf::new(&rec.normal);
+        let direction = emission.generate();
+        let pdf_dir = emission.value(&direction);
+        let emitted = rec.material.emitted(&Ray::new(-direction, rec.p), &rec);
+        if pdf_pos <= 0. || pdf_dir <= 0. || emitted.max_component() <= 0. {
+            return path;
+        }
+        let cosine = rec.normal.dot(&direction.normalize()).abs();
+        let mut beta = emitted.clone() * (cosine / (pdf_pos * pdf_dir));
+        let mut r = Ray::new(direction, rec.p);
+        path.push(Vertex {
+            rec,
+            beta: emitted * (1. / pdf_pos),
+            delta: false,
+        });
+
+        while path.len() < self.max_depth {
+            let Some(rec) = scene.world.hit(&r, &(0.001..f32::INFINITY)) else {
+                break;
+            };
+            let Some((weight, scattered, delta)) = scatter(&r, &rec) else {
+                break;
+            };
+            path.push(Vertex {
+                rec,
+                beta: beta.clone(),
+                delta,
+            });
+            beta = beta * weight;
+            r = scattered;
+        }
+
+        path
+    }
+
+    /// Samples a point on the lights as seen from `vertex`, the same way
+    /// the path tracer does, returning the unweighted contribution and the
+    /// point on the light
+    fn connect_to_light<'a>(
+        &self,
+        r_in: &Ray,
+        vertex: &Vertex,
+        scene: &Scene<'a>,
+    ) -> Option<(Color, HitRecord<'a>)> {
+        if scene.lights.is_empty() {
+            return None;
+        }
+        let light_pdf = HittablePdf::new(scene.lights, vertex.rec.p);
+        let shadow_ray = Ray::new(light_pdf.generate(), vertex.rec.p);
+        let pdf = light_pdf.value(&shadow_ray.direction);
+        if pdf <= 0. {
+            return None;
+        }
+        let light_rec = scene.world.hit(&shadow_ray, &(0.001..f32::INFINITY))?;
+        let emitted = light_rec.material.emitted(&shadow_ray, &light_rec);
+        if emitted.max_component() <= 0. {
+            return None;
+        }
+        let f = vertex.rec.material.eval(r_in, &vertex.rec, &shadow_ray);
+        Some((vertex.beta.clone() * f * emitted * (1. / pdf), light_rec))
+    }
+}
+
+/// Ray that reached the camera subpath vertex `t`
+fn incoming(camera: &Point3, camera_path: &[Vertex], t: usize) -> Ray {
+    let from = if t == 0 {
+        *camera
+    } else {
+        camera_path[t - 1].rec.p
+    };
+    Ray::new(camera_path[t].rec.p - from, from)
+}
+
+/// Scatters `r` off the hit, returning the weight of the bounce, the
+/// scattered ray and whether the bounce was specular
+fn scatter(r: &Ray, rec: &HitRecord) -> Option<(Color, Ray, bool)> {
+    match rec.material.scatter(r, rec)? {
+        ScatterRecord::Specular { attenuation, ray } | ScatterRecord::Walk { attenuation, ray } => {
+            Some((attenuation, ray, true))
+        }
+        ScatterRecord::Pdf(pdf) => {
+            let scattered = Ray::new(pdf.generate(), rec.p);
+            let pdf_value = pdf.value(&scattered.direction);
+            if pdf_value <= 0. {
+                return None;
+            }
+            let weight = rec.material.eval(r, rec, &scattered) * (1. / pdf_value);
+            Some((weight, scattered, false))
+        }
+    }
+}
+
+/// Unweighted contribution of joining the camera subpath at `vertex` with
+/// the light subpath vertex `s`
+fn connect(r_in: &Ray, vertex: &Vertex, light_path: &[Vertex], s: usize, scene: &Scene) -> Color {
+    let light_vertex = &light_path[s];
+    let to_light = Ray::new(light_vertex.rec.p - vertex.rec.p, vertex.rec.p);
+    let f_camera = vertex.rec.material.eval(r_in, &vertex.rec, &to_light);
+    if f_camera.max_component() <= 0. {
+        return Color::black();
+    }
+
+    let light_in = Ray::new(
+        light_vertex.rec.p - light_path[s - 1].rec.p,
+        light_path[s - 1].rec.p,
+    );
+    let to_camera = Ray::new(vertex.rec.p - light_vertex.rec.p, light_vertex.rec.p);
+    let f_light = light_vertex
+        .rec
+        .material
+        .eval(&light_in, &light_vertex.rec, &to_camera);
+    if f_light.max_component() <= 0. {
+        return Color::black();
+    }
+
+    // Both sides have to see each other
+    if scene.world.hit(&to_light, &(0.001..0.999)).is_some() {
+        return Color::black();
+    }
+
+    let dist_squared = to_light.direction.magnitude_squared();
+    vertex.beta.clone() * f_camera * f_light * light_vertex.beta.clone() * (1. / dist_squared)
+}
+
+/// Density of the material at `rec` scattering light arriving from `from`
+/// towards `to`, with respect to solid angle
+fn scatter_pdf(rec: &HitRecord, from: &Point3, to: &Point3) -> f32 {
+    let r_in = Ray::new(rec.p - from, *from);
+    let rec = HitRecord::new(rec.p, rec.outward_normal(), rec.t, &r_in, rec.material);
+    match rec.material.scatter(&r_in, &rec) {
+        Some(ScatterRecord::Pdf(pdf)) => pdf.value(&(to - rec.p)),
+        _ => 0.,
+    }
+}
+
+/// Converts a density with respect to solid angle at `from` into a density
+/// with respect to area at `to`
+fn to_area(pdf: f32, from: &Point3, to: &HitRecord) -> f32 {
+    let direction = to.p - from;
+    let dist_squared = direction.magnitude_squared();
+    pdf * to.normal.dot(&direction).abs() / (dist_squared.sqrt() * dist_squared)
+}
+
+/// Power heuristic weight of building the path from `s` light subpath
+/// vertices, out of every strategy that could have built it.
+///
+/// `path` lists the vertices from the camera to the light, along with
+/// whether they are specular. The light subpath starts on the last vertex.
+fn mis_weight(camera: &Point3, path: &[(&HitRecord, bool)], s: usize, scene: &Scene) -> f32 {
+    let k = path.len();
+    if k < 2 {
+        return 1.;
+    }
+    // Vertex `i` of the full path, where vertex zero is the camera
+    let point = |i: usize| if i == 0 { *camera } else { path[i - 1].0.p };
+    let rec = |i: usize| path[i - 1].0;
+    let delta = |i: usize| i > 0 && path[i - 1].1;
+
+    // Densities of sampling each vertex from the camera side and from the
+    // light side, with respect to area. Vertices sampled from a specular
+    // vertex get a density of one, since the same delta shows up in every
+    // strategy able to build the path.
+    let mut forward = vec![1f32; k + 1];
+    let mut reverse = vec![1f32; k + 1];
+    for (i, forward) in forward.iter_mut().enumerate().skip(2) {
+        if !delta(i - 1) {
+            let pdf = scatter_pdf(rec(i - 1), &point(i - 2), &point(i));
+            *forward = to_area(pdf, &point(i - 1), rec(i));
+        }
+    }
+    // Light subpaths start on a point picked over the area of the lights,
+    // while connecting to a new point on the lights picks it like the path
+    // tracer does, which may favor the parts facing the vertex
+    let light = rec(k);
+    let towards_light = point(k) - point(k - 1);
+    reverse[k] = scene
+        .lights
+        .surface_pdf_value(&point(k - 1), &towards_light);
+    let light_pdf = scene.lights.pdf_value(&point(k - 1), &towards_light);
+    let mut connection_pdf = to_area(light_pdf, &point(k - 1), light);
+    let to_previous = point(k - 1) - point(k);
+    let emission_pdf = (light.normal.dot(&to_previous.normalize()) / PI).max(0.);
+    reverse[k - 1] = to_area(emission_pdf, &point(k), rec(k - 1));
+    for i in (1..k - 1).rev() {
+        if !delta(i + 1) {
+            let pdf = scatter_pdf(rec(i + 1), &point(i + 2), &point(i));
+            reverse[i] = to_area(pdf, &point(i + 1), rec(i));
+        }
+    }
+
+    // Products of area densities underflow on long paths through large
+    // scenes. Dividing both densities of a vertex by the larger scales
+    // every strategy alike, which leaves the weights unchanged.
+    for i in 0..=k {
+        let mut scale = forward[i].max(reverse[i]);
+        if i == k {
+            scale = scale.max(connection_pdf);
+        }
+        if scale > 0. {
+            forward[i] /= scale;
+            reverse[i] /= scale;
+            if i == k {
+                connection_pdf /= scale;
+            }
+        }
+    }
+
+    // Density of the strategy using `light` vertices from the light side.
+    // The camera subpath always keeps the camera and the first hit.
+    let strategy_pdf = |light: usize| -> f32 {
+        let valid = light == 0 || (!delta(k - light) && !delta(k - light + 1));
+        if !valid {
+            return 0.;
+        }
+        let camera_side = (1..=k - light).map(|i| forward[i]).product::<f32>();
+        if light == 1 {
+            return camera_side * connection_pdf;
+        }
+        camera_side * (k - light + 1..=k).map(|i| reverse[i]).product::<f32>()
+    };
+
+    let pdf = strategy_pdf(s);
+    let sum = (0..k).map(|light| strategy_pdf(light).powi(2)).sum::<f32>();
+    if sum > 0. {
+        pdf * pdf / sum
+    } else {
+        0.
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        hittable::HittableList,
+        material::{DiffuseLight, Lambertian},
+        quad::Quad,
+        sky::Gradient,
+        sphere::Sphere,
+        vec3::Vec3,
+    };
+    use std::sync::Arc;
+
+    /// Sum of the weights of every strategy for a path from the camera to
+    /// the floor, the back wall and a light on the ceiling
+    fn total_weight<H: Hittable + Send + 'static>(light: H, target: Vec3) -> f32 {
+        let mut world = HittableList::default();
+        let mut lights = HittableList::default();
+        world.push(Quad::new(
+            Vec3(-1., 0., -2.),
+            Vec3(2., 0., 0.),
+            Vec3(0., 0., 2.),
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ));
+        world.push(Quad::new(
+            Vec3(-1., 0., -2.),
+            Vec3(0., 2., 0.),
+            Vec3(2., 0., 0.),
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ));
+        let light = Arc::new(light);
+        world.push(light.clone());
+        lights.push(light);
+        let background = Gradient::new(Color::black(), Color::black());
+        let scene = Scene {
+            world: &world,
+            lights: &lights,
+            background: &background,
+        };
+
+        let camera = Vec3(0., 1., 1.);
+        let hit = |from: Vec3, to: Vec3| {
+            world
+                .hit(&Ray::new(to - from, from), &(0.001..f32::INFINITY))
+                .unwrap()
+        };
+        let floor = hit(camera, Vec3(0.2, 0., -1.));
+        let wall = hit(floor.p, Vec3(-0.3, 1.2, -2.));
+        let ceiling = hit(wall.p, target);
+        let path = [(&floor, false), (&wall, false), (&ceiling, false)];
+
+        (0..path.len())
+            .map(|s| mis_weight(&camera, &path, s, &scene))
+            .sum()
+    }
+
+    #[test]
+    fn mis_weights_sum_to_one() {
+        // Facing down
+        let quad = Quad::new(
+            Vec3(-0.5, 2., -1.5),
+            Vec3(0., 0., 1.),
+            Vec3(1., 0., 0.),
+            DiffuseLight::new(Color::new(4., 4., 4.)),
+        );
+        let total = total_weight(quad, Vec3(0.1, 2., -1.));
+        assert!((total - 1.).abs() < 1e-4, "{total}");
+
+        // Light subpaths start anywhere on a sphere, while connections only
+        // pick points on the side facing the vertex
+        let sphere = Sphere::new(
+            Vec3(0., 1.7, -1.),
+            0.2,
+            DiffuseLight::new(Color::new(4., 4., 4.)),
+        );
+        let total = total_weight(sphere, Vec3(0., 1.7, -1.));
+        assert!((total - 1.).abs() < 1e-4, "{total}");
+    }
+}
@@ -0,0 +1,287 @@
+use super::{Integrator, Scene};
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    pdf::{CosinePdf, Pdf},
+    ray::Ray,
+    vec3::Vec3,
+};
+
+/// Fraction of the hemisphere above the first hit that is not blocked
+/// within `distance`. Rays that miss everything count as unoccluded.
+pub struct AmbientOcclusion {
+    pub distance: f32,
+    pub samples: usize,
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        AmbientOcclusion {
+            distance: 1.,
+            samples: 16,
+        }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn ray_color_with_hit<'a>(
+        &self,
+        _: Ray,
+        hit: Option<HitRecord<'a>>,
+        scene: &Scene<'a>,
+    ) -> (Color, usize) {
+        let Some(rec) = hit else {
+            return (Color::new(1., 1., 1.), 0);
+        };
+
+        // Cosine weighted directions make the estimate the ambient light a
+        // lambertian surface would see
+        let pdf = CosinePdf::new(&rec.normal);
+        let unoccluded = (0..self.samples)
+            .filter(|_| {
+                let ray = Ray::new(pdf.generate().normalize(), rec.p);
+                scene.world.hit(&ray, &(0.001..self.distance)).is_none()
+            })
+            .count();
+        let visibility = (unoccluded as f32) / (self.samples as f32);
+
+        (Color::new(visibility, visibility, visibility), 1)
+    }
+}
+
+/// Shading normal of the first hit, mapped from `-1..1` into `0..1`
+pub struct Normals;
+
+impl Integrator for Normals {
+    fn ray_color_with_hit<'a>(
+        &self,
+        _: Ray,
+        hit: Option<HitRecord<'a>>,
+        _: &Scene<'a>,
+    ) -> (Color, usize) {
+        match hit {
+            Some(rec) => {
+                let n = rec.normal.normalize();
+                (
+                    Color::new(0.5 * (n.0 + 1.), 0.5 * (n.1 + 1.), 0.5 * (n.2 + 1.)),
+                    1,
+                )
+            }
+            None => (Color::black(), 0),
+        }
+    }
+}
+
+/// Distance from the camera to the first hit along the direction it looks
+/// in, from black at the camera to white at `far`. Rays that miss
+/// everything are white.
+pub struct Depth {
+    /// Direction the camera looks in, of unit length
+    pub forward: Vec3,
+    pub far: f32,
+}
+
+impl Integrator for Depth {
+    fn ray_color_with_hit<'a>(
+        &self,
+        r: Ray,
+        hit: Option<HitRecord<'a>>,
+        _: &Scene<'a>,
+    ) -> (Color, usize) {
+        match hit {
+            Some(rec) => {
+                let depth = (rec.p - r.origin).dot(&self.forward) / self.far;
+                let depth = depth.min(1.);
+                (Color::new(depth, depth, depth), 1)
+            }
+            None => (Color::new(1., 1., 1.), 0),
+        }
+    }
+}
+
+/// Base color of the material at the first hit
+pub struct Albedo;
+
+impl Integrator for Albedo {
+    fn ray_color_with_hit<'a>(
+        &self,
+        _: Ray,
+        hit: Option<HitRecord<'a>>,
+        _: &Scene<'a>,
+    ) -> (Color, usize) {
+        match hit {
+            Some(rec) => (rec.material.albedo(&rec), 1),
+            None => (Color::black(), 0),
+        }
+    }
+}
+
+/// A color unique to the object at the first hit. Objects own their
+/// materials, so this tells materials apart as well.
+pub struct MaterialId;
+
+impl Integrator for MaterialId {
+    fn ray_color_with_hit<'a>(
+        &self,
+        _: Ray,
+        hit: Option<HitRecord<'a>>,
+        _: &Scene<'a>,
+    ) -> (Color, usize) {
+        match hit {
+            Some(rec) => (id_color(rec.object_id), 1),
+            None => (Color::black(), 0),
+        }
+    }
+}
+
+/// Hashes the ID of an object into a color
+pub(crate) fn id_color(object_id: usize) -> Color {
+    // A step of splitmix64, so consecutive IDs get unrelated colors
+    let mut h = (object_id as u64).wrapping_add(0x9e3779b97f4a7c15);
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+    h ^= h >> 31;
+    let channel = |shift: u64| ((h >> shift) & 0xff) as f32 / 255.;
+    Color::new(channel(0), channel(8), channel(16))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        hittable::HittableList, material::Lambertian, quad::Quad, sky::Gradient, sphere::Sphere,
+    };
+
+    fn scene_with<'a>(world: &'a HittableList, background: &'a Gradient) -> Scene<'a> {
+        Scene {
+            world,
+            lights: world,
+            background,
+        }
+    }
+
+    #[test]
+    fn ambient_occlusion_open_sky() {
+        let mut world = HittableList::default();
+        world.push(Sphere::from((0., -100., 0., 99.)));
+        let background = Gradient::default();
+        let scene = scene_with(&world, &background);
+
+        let ray = Ray::new(Vec3(0., -1., 0.), Vec3(0., 0., 0.));
+        let (color, _) = AmbientOcclusion::default().ray_color(ray, &scene);
+
+        assert_eq!(color, Color::new(1., 1., 1.));
+    }
+
+    #[test]
+    fn ambient_occlusion_inside_sphere() {
+        let mut world = HittableList::default();
+        world.push(Sphere::from((0., 0., 0., 0.4)));
+        let background = Gradient::default();
+        let scene = scene_with(&world, &background);
+
+        let ray = Ray::new(Vec3(0., -1., 0.), Vec3(0., 0., 0.));
+        let (color, _) = AmbientOcclusion::default().ray_color(ray, &scene);
+
+        assert_eq!(color, Color::black());
+    }
+
+    #[test]
+    fn normals_facing_camera() {
+        let mut world = HittableList::default();
+        world.push(Sphere::from((0., 0., -2., 0.5)));
+        let background = Gradient::default();
+        let scene = scene_with(&world, &background);
+
+        let ray = Ray::new(Vec3(0., 0., -1.), Vec3(0., 0., 0.));
+        let (color, bounces) = Normals.ray_color(ray, &scene);
+
+        assert_eq!(color, Color::new(0.5, 0.5, 1.));
+        assert_eq!(bounces, 1);
+    }
+
+    #[test]
+    fn depth_is_linear() {
+        let mut world = HittableList::default();
+        world.push(Sphere::from((0., 0., -2., 0.5)));
+        let background = Gradient::default();
+        let scene = scene_with(&world, &background);
+        let depth = Depth {
+            forward: Vec3(0., 0., -1.),
+            far: 3.,
+        };
+
+        let (color, _) = depth.ray_color(Ray::new(Vec3(0., 0., -1.), Vec3(0., 0., 0.)), &scene);
+        assert!((color[0] - 0.5).abs() < 1e-5);
+        let (color, _) = depth.ray_color(Ray::new(Vec3(0., 1., 0.), Vec3(0., 0., 0.)), &scene);
+        assert_eq!(color, Color::new(1., 1., 1.));
+    }
+
+    #[test]
+    fn depth_is_along_the_view() {
+        // A wall facing the camera is equally deep everywhere
+        let mut world = HittableList::default();
+        world.push(Quad::new(
+            Vec3(-2., -2., -2.),
+            Vec3(4., 0., 0.),
+            Vec3(0., 4., 0.),
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ));
+        let background = Gradient::default();
+        let scene = scene_with(&world, &background);
+        let depth = Depth {
+            forward: Vec3(0., 0., -1.),
+            far: 4.,
+        };
+
+        let origin = Vec3(0., 0., 0.);
+        for direction in [Vec3(0., 0., -1.), Vec3(0.5, 0., -1.), Vec3(-0.7, 0.6, -1.)] {
+            let (color, _) = depth.ray_color(Ray::new(direction, origin), &scene);
+            assert!((color[0] - 0.5).abs() < 1e-5, "{color:?}");
+        }
+    }
+
+    #[test]
+    fn albedo_of_first_hit() {
+        let mut world = HittableList::default();
+        world.push(Sphere::new(
+            Vec3(0., 0., -2.),
+            0.5,
+            Lambertian::new(Color::new(0.2, 0.4, 0.6)),
+        ));
+        let background = Gradient::default();
+        let scene = scene_with(&world, &background);
+
+        let ray = Ray::new(Vec3(0., 0., -1.), Vec3(0., 0., 0.));
+        let (color, _) = Albedo.ray_color(ray, &scene);
+
+        assert_eq!(color, Color::new(0.2, 0.4, 0.6));
+    }
+
+    #[test]
+    fn material_ids_differ() {
+        let two_spheres = || {
+            let mut world = HittableList::default();
+            world.push(Sphere::from((-1., 0., -2., 0.5)));
+            world.push(Sphere::from((1., 0., -2., 0.5)));
+            world
+        };
+        let world = two_spheres();
+        let background = Gradient::default();
+        let scene = scene_with(&world, &background);
+
+        let origin = Vec3(0., 0., 0.);
+        let (left, _) = MaterialId.ray_color(Ray::new(Vec3(-1., 0., -1.5), origin), &scene);
+        let (again, _) = MaterialId.ray_color(Ray::new(Vec3(-1., 0.1, -1.5), origin), &scene);
+        let (right, _) = MaterialId.ray_color(Ray::new(Vec3(1., 0., -1.5), origin), &scene);
+
+        assert_eq!(left, again);
+        assert_ne!(left, right);
+
+        // The same scene built again gets the same colors
+        let rebuilt = two_spheres();
+        let scene = scene_with(&rebuilt, &background);
+        let (rebuilt_left, _) = MaterialId.ray_color(Ray::new(Vec3(-1., 0., -1.5), origin), &scene);
+        assert_eq!(left, rebuilt_left);
+    }
+}
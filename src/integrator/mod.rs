@@ -0,0 +1,46 @@
+use crate::{
+    color::Color,
+    hittable::{HitRecord, Hittable, HittableList},
+    ray::Ray,
+    sky::Background,
+};
+
+pub mod bdpt;
+pub mod debug;
+pub mod path;
+pub mod photon;
+pub mod spectral;
+
+/// Everything an integrator can see while tracing a ray
+pub struct Scene<'a> {
+    pub world: &'a dyn Hittable,
+    /// Emissive objects of the world that can be sampled directly
+    pub lights: &'a HittableList,
+    pub background: &'a dyn Background,
+}
+
+/// Computes the color seen along camera rays. Different integrators trade
+/// physical accuracy for speed, or show something other than light
+/// altogether.
+pub trait Integrator: Sync {
+    /// Called once before any ray is traced, for integrators that need to
+    /// look at the whole scene first
+    fn preprocess(&mut self, _scene: &Scene) {}
+
+    /// Color arriving at the camera along `r`, which first hits `hit`,
+    /// together with the number of surfaces the path hit. The camera looks
+    /// the first hit up itself, to share it with the auxiliary images.
+    fn ray_color_with_hit<'a>(
+        &self,
+        r: Ray,
+        hit: Option<HitRecord<'a>>,
+        scene: &Scene<'a>,
+    ) -> (Color, usize);
+
+    /// Like `ray_color_with_hit`, looking up the first hit
+    #[cfg(test)]
+    fn ray_color(&self, r: Ray, scene: &Scene) -> (Color, usize) {
+        let hit = scene.world.hit(&r, &(0.001..f32::INFINITY));
+        self.ray_color_with_hit(r, hit, scene)
+    }
+}
@@ -0,0 +1,199 @@
+use super::{Integrator, Scene};
+use crate::{
+    color::Color,
+    hittable::{HitRecord, Hittable},
+    material::ScatterRecord,
+    pdf::{power_heuristic, HittablePdf, MixturePdf, Pdf},
+    ray::Ray,
+};
+use rand::Rng;
+
+/// How lights are found at non-specular bounces
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LightSampling {
+    /// Lights are only found when a scattered ray happens to hit them
+    None,
+    /// Sample the lights and the BSDF once each, combined with the power
+    /// heuristic
+    #[default]
+    Mis,
+    /// Draw a single direction from an even mix of the lights and the BSDF,
+    /// as done in "The Rest of Your Life"
+    Mixture,
+}
+
+/// Unidirectional path tracer
+pub struct PathTracer {
+    /// Maximum number of surfaces a path may hit
+    pub max_depth: usize,
+    /// Number of bounces before Russian roulette may end a path
+    pub roulette_depth: usize,
+    pub light_sampling: LightSampling,
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        PathTracer {
+            max_depth: 50,
+            roulette_depth: 5,
+            light_sampling: LightSampling::default(),
+        }
+    }
+}
+
+impl Integrator for PathTracer {
+    /// When a bounce samples the lights, light reached by the scattered ray
+    /// is weighted against the light sample with the power heuristic. Past
+    /// `roulette_depth` bounces, paths are randomly ended with a probability
+    /// that grows as their throughput drops, and the survivors are weighted
+    /// up to compensate.
+    fn ray_color_with_hit<'a>(
+        &self,
+        r: Ray,
+        hit: Option<HitRecord<'a>>,
+        scene: &Scene<'a>,
+    ) -> (Color, usize) {
+        let mut rng = rand::thread_rng();
+        let mut r = r;
+        let mut color = Color::black();
+        // Product of the weights of every bounce so far
+        let mut throughput = Color::new(1., 1., 1.);
+        // Density the last bounce sampled `r` with, when that bounce also
+        // sampled the lights
+        let mut bsdf_pdf: Option<f32> = None;
+        let mut bounces = 0;
+        let mut first_hit = Some(hit);
+
+        while bounces < self.max_depth {
+            let hit = first_hit
+                .take()
+                .unwrap_or_else(|| scene.world.hit(&r, &(0.001..f32::INFINITY)));
+            let Some(rec) = hit else {
+                // This is the background branch
+                let mut background = r.illuminant(&scene.background.color(&r.direction));
+                if let Some(sun) = scene.background.sun() {
+                    let weight =
+                        bsdf_pdf.map_or(1., |pdf| power_heuristic(pdf, sun.value(&r.direction)));
+                    background += weight * r.illuminant(&sun.radiance(&r.direction));
+                }
+                color += throughput.clone() * background;
+                break;
+            };
+            bounces += 1;
+
+            let mut emitted = rec.material.emitted(&r, &rec);
+            if let Some(pdf) = bsdf_pdf {
+                let light_pdf = if scene.lights.is_empty() {
+                    0.
+                } else {
+                    scene.lights.pdf_value(&r.origin, &r.direction)
+                };
+                emitted = power_heuristic(pdf, light_pdf) * emitted;
+            }
+            color += throughput.clone() * emitted;
+
+            let (weight, scattered, next_pdf) = match rec.material.scatter(&r, &rec) {
+                None => break,
+                Some(ScatterRecord::Specular { attenuation, ray }) => (attenuation, ray, None),
+                Some(ScatterRecord::Walk { attenuation, ray }) => {
+                    // Steps through a medium are not bounces
+                    bounces -= 1;
+                    (attenuation, ray, None)
+                }
+                Some(ScatterRecord::Pdf(pdf)) => match self.light_sampling {
+                    LightSampling::None => {
+                        let scattered = r.spawn(pdf.generate(), rec.p);
+                        let pdf_value = pdf.value(&scattered.direction);
+                        if pdf_value <= 0. {
+                            break;
+                        }
+                        let weight = rec.material.eval(&r, &rec, &scattered) * (1. / pdf_value);
+                        (weight, scattered, None)
+                    }
+                    LightSampling::Mis => {
+                        let direct = sample_lights(&r, &rec, pdf.as_ref(), scene);
+                        color += throughput.clone() * direct;
+                        let scattered = r.spawn(pdf.generate(), rec.p);
+                        let pdf_value = pdf.value(&scattered.direction);
+                        if pdf_value <= 0. {
+                            break;
+                        }
+                        let weight = rec.material.eval(&r, &rec, &scattered) * (1. / pdf_value);
+                        (weight, scattered, Some(pdf_value))
+                    }
+                    LightSampling::Mixture => {
+                        let light_pdf = HittablePdf::new(scene.lights, rec.p);
+                        let mut pdfs: Vec<&dyn Pdf> = vec![pdf.as_ref()];
+                        if !scene.lights.is_empty() {
+                            pdfs.push(&light_pdf);
+                        }
+                        if let Some(sun) = scene.background.sun() {
+                            pdfs.push(sun);
+                        }
+                        let mixture = MixturePdf::new(pdfs);
+
+                        let scattered = r.spawn(mixture.generate(), rec.p);
+                        let pdf_value = mixture.value(&scattered.direction);
+                        if pdf_value <= 0. {
+                            break;
+                        }
+                        let weight = rec.material.eval(&r, &rec, &scattered) * (1. / pdf_value);
+                        (weight, scattered, None)
+                    }
+                },
+            };
+
+            throughput = throughput * weight;
+            if bounces > self.roulette_depth {
+                let survival = throughput.max_component().min(0.95);
+                if rng.gen::<f32>() >= survival {
+                    break;
+                }
+                throughput = throughput * (1. / survival);
+            }
+
+            bsdf_pdf = next_pdf;
+            r = scattered;
+        }
+
+        (color, bounces)
+    }
+}
+
+/// Direct lighting at a non-specular hit, estimated with one shadow ray
+/// towards the lights and one towards the sun. Each is weighted against
+/// `bsdf_pdf` having sampled the same direction.
+pub(super) fn sample_lights(r: &Ray, rec: &HitRecord, bsdf_pdf: &dyn Pdf, scene: &Scene) -> Color {
+    let mut direct = Color::black();
+
+    if !scene.lights.is_empty() {
+        let light_pdf = HittablePdf::new(scene.lights, rec.p);
+        let shadow_ray = r.spawn(light_pdf.generate(), rec.p);
+        let pdf = light_pdf.value(&shadow_ray.direction);
+        if pdf > 0. {
+            if let Some(light_rec) = scene.world.hit(&shadow_ray, &(0.001..f32::INFINITY)) {
+                let emitted = light_rec.material.emitted(&shadow_ray, &light_rec);
+                let weight = power_heuristic(pdf, bsdf_pdf.value(&shadow_ray.direction));
+                direct += rec.material.eval(r, rec, &shadow_ray) * emitted * (weight / pdf);
+            }
+        }
+    }
+
+    if let Some(sun) = scene.background.sun() {
+        let shadow_ray = r.spawn(sun.generate(), rec.p);
+        let pdf = sun.value(&shadow_ray.direction);
+        if pdf > 0.
+            && scene
+                .world
+                .hit(&shadow_ray, &(0.001..f32::INFINITY))
+                .is_none()
+        {
+            let weight = power_heuristic(pdf, bsdf_pdf.value(&shadow_ray.direction));
+            direct += rec.material.eval(r, rec, &shadow_ray)
+                * shadow_ray.illuminant(&sun.radiance(&shadow_ray.direction))
+                * (weight / pdf);
+        }
+    }
+
+    direct
+}
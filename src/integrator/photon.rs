@@ -0,0 +1,518 @@
+use super::{path::sample_lights, Integrator, Scene};
+use crate::{
+    color::Color,
+    hittable::{HitRecord, Hittable},
+    material::ScatterRecord,
+    pdf::{power_heuristic, CosinePdf, Pdf},
+    ray::Ray,
+    vec3::{onb::Onb, Point3, Vec3},
+};
+use rand::Rng;
+use rayon::prelude::*;
+use std::{collections::BinaryHeap, f32::consts::PI, ops::Range};
+
+/// Path tracer that takes caustics from a photon map instead of finding
+/// them by chance.
+///
+/// Before rendering, photons are shot from the lights and the sun, and the
+/// ones that land on a non-specular surface after bouncing off specular
+/// ones are stored. Paths that reach a light through specular bounces right
+/// after a non-specular one are then ignored, and the photons near every
+/// non-specular hit are counted instead.
+///
+/// With more than one pass the caustics are progressive: each pass has its
+/// own photons and a smaller search radius than the last, following Knaus
+/// and Zwicker, "Progressive Photon Mapping: A Probabilistic Approach"
+/// (2011).
+pub struct PhotonMapper {
+    /// Maximum number of surfaces a path or photon may hit
+    pub max_depth: usize,
+    /// Number of bounces before Russian roulette may end a path
+    pub roulette_depth: usize,
+    /// Number of photons shot in each pass
+    pub photons: usize,
+    pub passes: usize,
+    /// Search radius of the first pass. When not given, it is picked so a
+    /// few dozen photons are found around a typical photon.
+    pub radius: Option<f32>,
+    /// How much of the photons are kept from one pass to the next, between
+    /// zero and one. Lower values shrink the radius faster.
+    pub alpha: f32,
+    /// Sphere that photons from the sun are shot at. Sun caustics on
+    /// surfaces outside of it are left to the path tracer. Without it, the
+    /// sun does not shoot photons at all.
+    pub sun_target: Option<(Point3, f32)>,
+    /// Photon map of every pass along with its search radius
+    maps: Vec<(PhotonMap, f32)>,
+}
+
+impl Default for PhotonMapper {
+    fn default() -> Self {
+        PhotonMapper {
+            max_depth: 50,
+            roulette_depth: 5,
+            photons: 200_000,
+            passes: 1,
+            radius: None,
+            alpha: 2. / 3.,
+            sun_target: None,
+            maps: Vec::new(),
+        }
+    }
+}
+
+impl Integrator for PhotonMapper {
+    fn preprocess(&mut self, scene: &Scene) {
+        self.maps.clear();
+        let mut radius = self.radius;
+        for pass in 0..self.passes {
+            let map = self.shoot_photons(scene);
+            let r = *radius.get_or_insert_with(|| map.typical_radius());
+            self.maps.push((map, r));
+            // r(i+1)^2 = r(i)^2 (i + alpha) / (i + 1), counting passes from one
+            let pass = (pass + 1) as f32;
+            radius = Some(r * ((pass + self.alpha) / (pass + 1.)).sqrt());
+        }
+    }
+
+    /// Same as the path tracer sampling lights with MIS, except for the
+    /// caustics
+    fn ray_color_with_hit<'a>(
+        &self,
+        r: Ray,
+        hit: Option<HitRecord<'a>>,
+        scene: &Scene<'a>,
+    ) -> (Color, usize) {
+        let mut rng = rand::thread_rng();
+        let mut r = r;
+        let mut color = Color::black();
+        let mut throughput = Color::new(1., 1., 1.);
+        let mut bsdf_pdf: Option<f32> = None;
+        // Most recent non-specular hit
+        let mut last_diffuse: Option<Point3> = None;
+        let mut bounces = 0;
+        let mut first_hit = Some(hit);
+
+        while bounces < self.max_depth {
+            // Every bounce since the last non-specular one was specular, so
+            // light found now is a caustic
+            let caustic = bsdf_pdf.is_none() && last_diffuse.is_some();
+
+            let hit = first_hit
+                .take()
+                .unwrap_or_else(|| scene.world.hit(&r, &(0.001..f32::INFINITY)));
+            let Some(rec) = hit else {
+                let mut background = scene.background.color(&r.direction);
+                if let Some(sun) = scene.background.sun() {
+                    let from_photons =
+                        caustic && last_diffuse.is_some_and(|p| self.in_sun_target(&p));
+                    if !from_photons {
+                        let weight = bsdf_pdf
+                            .map_or(1., |pdf| power_heuristic(pdf, sun.value(&r.direction)));
+                        background += weight * sun.radiance(&r.direction);
+                    }
+                }
+                color += throughput.clone() * background;
+                break;
+            };
+            bounces += 1;
+
+            if !caustic {
+                let mut emitted = rec.material.emitted(&r, &rec);
+                if let Some(pdf) = bsdf_pdf {
+                    let light_pdf = if scene.lights.is_empty() {
+                        0.
+                    } else {
+                        scene.lights.pdf_value(&r.origin, &r.direction)
+                    };
+                    emitted = power_heuristic(pdf, light_pdf) * emitted;
+                }
+                color += throughput.clone() * emitted;
+            }
+
+            let (weight, scattered, next_pdf) = match rec.material.scatter(&r, &rec) {
+                None => break,
+                Some(ScatterRecord::Specular { attenuation, ray }) => (attenuation, ray, None),
+                Some(ScatterRecord::Walk { attenuation, ray }) => {
+                    // Steps through a medium are not bounces
+                    bounces -= 1;
+                    (attenuation, ray, None)
+                }
+                Some(ScatterRecord::Pdf(pdf)) => {
+                    let direct = sample_lights(&r, &rec, pdf.as_ref(), scene);
+                    color += throughput.clone() * (direct + self.caustics(&r, &rec));
+                    last_diffuse = Some(rec.p);
+
+                    let scattered = Ray::new(pdf.generate(), rec.p);
+                    let pdf_value = pdf.value(&scattered.direction);
+                    if pdf_value <= 0. {
+                        break;
+                    }
+                    let weight = rec.material.eval(&r, &rec, &scattered) * (1. / pdf_value);
+                    (weight, scattered, Some(pdf_value))
+                }
+            };
+
+            throughput = throughput * weight;
+            if bounces > self.roulette_depth {
+                let survival = throughput.max_component().min(0.95);
+                if rng.gen::<f32>() >= survival {
+                    break;
+                }
+                throughput = throughput * (1. / survival);
+            }
+
+            bsdf_pdf = next_pdf;
+            r = scattered;
+        }
+
+        (color, bounces)
+    }
+}
+
+impl PhotonMapper {
+    fn in_sun_target(&self, p: &Point3) -> bool {
+        self.sun_target
+            .is_some_and(|(center, radius)| (p - center).magnitude_squared() <= radius * radius)
+    }
+
+    /// Caustic light leaving `rec` along the reverse of `r`, averaged over
+    /// every pass
+    fn caustics(&self, r: &Ray, rec: &HitRecord) -> Color {
+        let mut total = Color::black();
+        for (map, radius) in &self.maps {
+            let mut flux = Color::black();
+            map.within(&rec.p, *radius, |photon| {
+                let towards_light = Ray::new(-photon.direction, rec.p);
+                let cosine = rec.normal.dot(&towards_light.direction);
+                if cosine > 0. {
+                    // The density of photons already accounts for the cosine
+                    let f = rec.material.eval(r, rec, &towards_light) * (1. / cosine);
+                    flux += f * photon.power.clone();
+                }
+            });
+            total += flux * (1. / (PI * radius * radius));
+        }
+        total * (1. / self.maps.len().max(1) as f32)
+    }
+
+    fn shoot_photons(&self, scene: &Scene) -> PhotonMap {
+        let sun = scene.background.sun().zip(self.sun_target);
+        let sources = usize::from(!scene.lights.is_empty()) + usize::from(sun.is_some());
+        if sources == 0 {
+            return PhotonMap::new(Vec::new());
+        }
+        // Each photon picks one of the sources, so it carries that much more
+        let scale = sources as f32 / self.photons as f32;
+
+        let photons = (0..self.photons)
+            .into_par_iter()
+            .filter_map(|_| {
+                let mut rng = rand::thread_rng();
+                let from_lights =
+                    !scene.lights.is_empty() && (sun.is_none() || rng.gen::<f32>() < 0.5);
+                let (ray, power) = if from_lights {
+                    let (rec, pdf) = scene.lights.sample_surface()?;
+                    let direction = CosinePdf::new(&rec.normal).generate();
+                    let emitted = rec.material.emitted(&Ray::new(-direction, rec.p), &rec);
+                    // Emitted radiance times cosine, over the densities of the
+                    // point and of the cosine weighted direction
+                    (Ray::new(direction, rec.p), emitted * (PI * scale / pdf))
+                } else {
+                    let (sun, (center, radius)) = sun?;
+                    let direction = sun.generate();
+                    let pdf = sun.value(&direction);
+                    if pdf <= 0. {
+                        return None;
+                    }
+                    let irradiance = sun.radiance(&direction) * (1. / pdf);
+                    // Start far outside the target, from a disk facing the sun
+                    let disk =
+                        Onb::new(&direction).transform(&(radius * Vec3::random_in_unit_disk()));
+                    let origin = center + disk + direction * (100. * radius);
+                    let power = irradiance * (PI * radius * radius * scale);
+                    (Ray::new(-direction, origin), power)
+                };
+                let photon = self.trace_photon(ray, power, scene)?;
+                if !from_lights && !self.in_sun_target(&photon.p) {
+                    return None;
+                }
+                Some(photon)
+            })
+            .collect();
+
+        PhotonMap::new(photons)
+    }
+
+    /// Follows a photon through specular bounces, returning where it lands
+    /// if that is on a non-specular surface
+    fn trace_photon(&self, r: Ray, power: Color, scene: &Scene) -> Option<Photon> {
+        let mut r = r;
+        let mut power = power;
+        let mut depth = 0;
+        while depth < self.max_depth {
+            let rec = scene.world.hit(&r, &(0.001..f32::INFINITY))?;
+            match rec.material.scatter(&r, &rec)? {
+                ScatterRecord::Specular { attenuation, ray } => {
+                    power = power * attenuation;
+                    r = ray;
+                    depth += 1;
+                }
+                ScatterRecord::Walk { attenuation, ray } => {
+                    power = power * attenuation;
+                    r = ray;
+                }
+                // Only photons that went through something specular make
+                // caustics; the rest of the light is path traced
+                ScatterRecord::Pdf(_) if depth > 0 => {
+                    return Some(Photon {
+                        p: rec.p,
+                        direction: r.direction.normalize(),
+                        power,
+                    });
+                }
+                ScatterRecord::Pdf(_) => return None,
+            }
+        }
+        None
+    }
+}
+
+/// Light carried to a surface
+struct Photon {
+    p: Point3,
+    /// Unit direction the photon was travelling in
+    direction: Vec3,
+    power: Color,
+}
+
+/// Photons stored as a balanced kd-tree. Every slice of the array is split
+/// at its middle photon, along the axis in which the slice is widest.
+struct PhotonMap {
+    photons: Vec<Photon>,
+    /// Axis every photon splits its slice along
+    axes: Vec<usize>,
+}
+
+impl PhotonMap {
+    fn new(mut photons: Vec<Photon>) -> Self {
+        let mut axes = vec![0; photons.len()];
+        build(&mut photons, &mut axes);
+        PhotonMap { photons, axes }
+    }
+
+    fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    /// Calls `f` on every photon closer than `radius` to `p`
+    fn within(&self, p: &Point3, radius: f32, mut f: impl FnMut(&Photon)) {
+        self.search(0..self.len(), p, radius * radius, &mut f);
+    }
+
+    fn search(&self, range: Range<usize>, p: &Point3, r2: f32, f: &mut impl FnMut(&Photon)) {
+        if range.is_empty() {
+            return;
+        }
+        let mid = range.start + range.len() / 2;
+        let photon = &self.photons[mid];
+        if (photon.p - p).magnitude_squared() <= r2 {
+            f(photon);
+        }
+        let d = p[self.axes[mid]] - photon.p[self.axes[mid]];
+        let (near, far) = if d < 0. {
+            (range.start..mid, mid + 1..range.end)
+        } else {
+            (mid + 1..range.end, range.start..mid)
+        };
+        self.search(near, p, r2, f);
+        if d * d <= r2 {
+            self.search(far, p, r2, f);
+        }
+    }
+
+    /// Distance from `p` to its `k`th closest photon, or infinity when there
+    /// are fewer photons
+    fn nearest(&self, p: &Point3, k: usize) -> f32 {
+        // Squared distances are never negative, so their bits sort the same
+        // way as the distances themselves
+        let mut closest = BinaryHeap::with_capacity(k + 1);
+        self.nearest_in(0..self.len(), p, k, &mut closest);
+        if closest.len() < k {
+            return f32::INFINITY;
+        }
+        closest
+            .peek()
+            .map_or(f32::INFINITY, |bits| f32::from_bits(*bits).sqrt())
+    }
+
+    fn nearest_in(&self, range: Range<usize>, p: &Point3, k: usize, closest: &mut BinaryHeap<u32>) {
+        if range.is_empty() {
+            return;
+        }
+        let mid = range.start + range.len() / 2;
+        let photon = &self.photons[mid];
+        closest.push((photon.p - p).magnitude_squared().to_bits());
+        if closest.len() > k {
+            closest.pop();
+        }
+        let d = p[self.axes[mid]] - photon.p[self.axes[mid]];
+        let (near, far) = if d < 0. {
+            (range.start..mid, mid + 1..range.end)
+        } else {
+            (mid + 1..range.end, range.start..mid)
+        };
+        self.nearest_in(near, p, k, closest);
+        let worst = closest
+            .peek()
+            .map_or(f32::INFINITY, |bits| f32::from_bits(*bits));
+        if closest.len() < k || d * d <= worst {
+            self.nearest_in(far, p, k, closest);
+        }
+    }
+
+    /// Median distance from a spread of the photons to their 32nd closest
+    /// neighbour
+    fn typical_radius(&self) -> f32 {
+        let step = (self.len() / 64).max(1);
+        let mut distances: Vec<f32> = (0..self.len())
+            .step_by(step)
+            .map(|i| self.nearest(&self.photons[i].p, 32))
+            .filter(|d| d.is_finite())
+            .collect();
+        if distances.is_empty() {
+            return 1.;
+        }
+        let mid = distances.len() / 2;
+        *distances.select_nth_unstable_by(mid, f32::total_cmp).1
+    }
+}
+
+fn build(photons: &mut [Photon], axes: &mut [usize]) {
+    if photons.is_empty() {
+        return;
+    }
+    let mut min = Vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+    let mut max = -min;
+    for photon in photons.iter() {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(photon.p[axis]);
+            max[axis] = max[axis].max(photon.p[axis]);
+        }
+    }
+    let extent = max - min;
+    let axis = (0..3)
+        .max_by(|a, b| extent[*a].total_cmp(&extent[*b]))
+        .unwrap();
+
+    let mid = photons.len() / 2;
+    photons.select_nth_unstable_by(mid, |a, b| a.p[axis].total_cmp(&b.p[axis]));
+    axes[mid] = axis;
+    let (left, right) = photons.split_at_mut(mid);
+    let (left_axes, right_axes) = axes.split_at_mut(mid);
+    build(left, left_axes);
+    build(&mut right[1..], &mut right_axes[1..]);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        hittable::HittableList,
+        material::{Dielectric, DiffuseLight, Lambertian},
+        quad::Quad,
+        sky::Gradient,
+        sphere::Sphere,
+    };
+    use std::sync::Arc;
+
+    fn random_map(n: usize) -> PhotonMap {
+        PhotonMap::new(
+            (0..n)
+                .map(|_| Photon {
+                    p: Vec3::random(),
+                    direction: Vec3(0., -1., 0.),
+                    power: Color::new(1., 1., 1.),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn within_matches_brute_force() {
+        let map = random_map(1000);
+        let p = Vec3(0.5, 0.5, 0.5);
+        let mut found = 0;
+        map.within(&p, 0.2, |_| found += 1);
+        let expected = map
+            .photons
+            .iter()
+            .filter(|photon| (photon.p - p).magnitude() <= 0.2)
+            .count();
+
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let map = random_map(1000);
+        let p = Vec3(0.3, 0.6, 0.1);
+        let mut distances: Vec<f32> = map
+            .photons
+            .iter()
+            .map(|photon| (photon.p - p).magnitude())
+            .collect();
+        distances.sort_by(f32::total_cmp);
+
+        assert!((map.nearest(&p, 10) - distances[9]).abs() < 1e-6);
+        assert_eq!(map.nearest(&p, 1001), f32::INFINITY);
+    }
+
+    #[test]
+    fn photons_only_stored_after_specular_bounces() {
+        // Glass ball under a light, above a diffuse floor
+        let mut world = HittableList::default();
+        let mut lights = HittableList::default();
+        world.push(Quad::new(
+            Vec3(-5., 0., -5.),
+            Vec3(0., 0., 10.),
+            Vec3(10., 0., 0.),
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ));
+        world.push(Sphere::new(Vec3(0., 1., 0.), 0.5, Dielectric::new(1.5)));
+        let light = Arc::new(Quad::new(
+            Vec3(-0.5, 3., -0.5),
+            Vec3(1., 0., 0.),
+            Vec3(0., 0., 1.),
+            DiffuseLight::new(Color::new(4., 4., 4.)),
+        ));
+        world.push(light.clone());
+        lights.push(light);
+        let background = Gradient::new(Color::black(), Color::black());
+        let scene = Scene {
+            world: &world,
+            lights: &lights,
+            background: &background,
+        };
+
+        let mut photon_mapper = PhotonMapper {
+            photons: 10_000,
+            passes: 2,
+            ..Default::default()
+        };
+        photon_mapper.preprocess(&scene);
+
+        assert_eq!(photon_mapper.maps.len(), 2);
+        let (map, radius) = &photon_mapper.maps[0];
+        assert!(map.len() > 0);
+        // Every photon went through the ball, and most were focused under it
+        assert!(map.photons.iter().all(|photon| photon.p.1.abs() < 1e-3));
+        let focused = map
+            .photons
+            .iter()
+            .filter(|photon| photon.p.0.hypot(photon.p.2) < 1.)
+            .count();
+        assert!(focused > map.len() / 2, "{focused} of {}", map.len());
+        assert!(photon_mapper.maps[1].1 < *radius);
+    }
+}
@@ -0,0 +1,37 @@
+use super::{Integrator, Scene};
+use crate::{color::Color, hittable::HitRecord, ray::Ray, spectrum::Wavelengths};
+
+/// Traces each camera ray at a few randomly drawn wavelengths with another
+/// integrator, then converts the light found back to RGB. Materials see the
+/// wavelengths on the rays they scatter, and upsample their RGB colors into
+/// spectra. Steps that depend on the wavelength, like refraction through a
+/// dispersive material, are sampled for one hero wavelength and weighted
+/// for all of them with multiple importance sampling, as in Wilkie et al.,
+/// "Hero Wavelength Spectral Sampling" (2014).
+///
+/// Only integrators that continue paths with `Ray::spawn` carry the
+/// wavelengths along, which is the path tracer.
+pub struct Spectral {
+    pub inner: Box<dyn Integrator>,
+}
+
+impl Integrator for Spectral {
+    fn preprocess(&mut self, scene: &Scene) {
+        self.inner.preprocess(scene);
+    }
+
+    fn ray_color_with_hit<'a>(
+        &self,
+        r: Ray,
+        hit: Option<HitRecord<'a>>,
+        scene: &Scene<'a>,
+    ) -> (Color, usize) {
+        let wavelengths = Wavelengths::sample();
+        let r = Ray {
+            wavelengths: Some(wavelengths),
+            ..r
+        };
+        let (radiance, bounces) = self.inner.ray_color_with_hit(r, hit, scene);
+        (wavelengths.to_rgb(&radiance), bounces)
+    }
+}
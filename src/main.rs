@@ -1,19 +1,36 @@
-use anyhow::{anyhow, Result};
+use anyhow::{bail, Context, Result};
 use material::Lambertian;
 use vec3::Vec3;
 
-use crate::{color::Color, hittable::HittableList, sphere::Sphere};
+use crate::{color::Color, hittable::HittableList, quad::Quad, sphere::Sphere, triangle::Triangle};
 use rand::{self, Rng};
+use std::sync::Arc;
 
+mod aov;
 mod camera;
 mod color;
 mod hittable;
+mod image;
+mod integrator;
 mod material;
+mod pdf;
+mod quad;
 mod ray;
+mod sky;
+mod spectrum;
 mod sphere;
+mod texture;
+mod tonemap;
+mod triangle;
 mod vec3;
-use clap::Parser;
-use std::path::PathBuf;
+use aov::Aovs;
+use clap::{Parser, ValueEnum};
+use image::Image;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "raytracerust")]
@@ -21,18 +38,387 @@ use std::path::PathBuf;
 #[command(about="CLI program that generates a ray tracing image", long_about=None)]
 pub struct Args {
     /// Output destination path. If not provided, the program will send the
-    /// output into stdout
+    /// output into stdout. Paths ending in `.png` are saved as PNG, `.exr`
+    /// as OpenEXR, `.hdr` as Radiance HDR, anything else as PPM.
     #[arg(short = 'o', long, value_name = "OUTPUT")]
     output: Option<PathBuf>,
+
+    /// Also write the normal, albedo, depth, position, object ID and
+    /// variance images, as layers of EXR output or as PFM files next to
+    /// other output
+    #[arg(long, requires = "output")]
+    aovs: bool,
+
+    /// Leave the background out, storing how much of each pixel is covered
+    /// in an alpha channel. Only PNG and EXR output have one.
+    #[arg(long, requires = "output")]
+    alpha: bool,
+
+    /// Primaries the colors of the scene are given and rendered in. PNG,
+    /// PPM and HDR output is converted to sRGB, while EXR output stays in
+    /// this space and is labeled with it.
+    #[arg(long, value_enum, default_value_t = WorkingSpace::LinearSrgb)]
+    working_space: WorkingSpace,
+
+    /// How radiance is brought into the range of PNG and PPM output
+    #[arg(long, value_enum, default_value_t = ToneMap::Clamp)]
+    tone_map: ToneMap,
+
+    /// Brightness adjustment in stops applied before tone mapping
+    #[arg(long, default_value_t = 0., allow_negative_numbers = true)]
+    exposure: f32,
+
+    /// Luminance that becomes white with the extended Reinhard tone map
+    #[arg(long, default_value_t = 4.)]
+    white_point: f32,
+
+    /// Bits per channel of PNG output
+    #[arg(long, value_enum, default_value_t = PngBitDepth::Eight)]
+    png_bit_depth: PngBitDepth,
+
+    /// How EXR output stores each value
+    #[arg(long, value_enum, default_value_t = ExrPixelType::Half)]
+    exr_pixel_type: ExrPixelType,
+
+    /// How EXR output is compressed
+    #[arg(long, value_enum, default_value_t = ExrCompression::Zip)]
+    exr_compression: ExrCompression,
+
+    /// Which scene to render
+    #[arg(long, value_enum, default_value_t = SceneKind::Random)]
+    scene: SceneKind,
+
+    /// What the image shows and how it is computed
+    #[arg(long, value_enum, default_value_t = Mode::Nee)]
+    mode: Mode,
+
+    /// Number of bounces before Russian roulette may end a path
+    #[arg(long, default_value_t = 5)]
+    roulette_depth: usize,
+
+    /// Carry light at randomly drawn wavelengths instead of as RGB. Only
+    /// the nee, path and mixture modes render spectrally.
+    #[arg(long)]
+    spectral: bool,
+
+    /// What rays that escape the scene see
+    #[arg(long, value_enum, default_value_t = SkyKind::Gradient)]
+    sky: SkyKind,
+
+    /// Angle of the sun above the horizon, in degrees
+    #[arg(long, default_value_t = 35.)]
+    sun_elevation: f32,
+
+    /// Angle of the sun around the vertical axis, in degrees
+    #[arg(long, default_value_t = 45.)]
+    sun_azimuth: f32,
+
+    /// Apparent diameter of the sun disk, in degrees
+    #[arg(long, default_value_t = 0.53)]
+    sun_diameter: f32,
+
+    /// Irradiance received from the sun, relative to a zenith luminance of
+    /// one
+    #[arg(long, default_value_t = 5.)]
+    sun_irradiance: f32,
+
+    /// Equirectangular `.hdr` panorama used by the environment map sky
+    #[arg(long, value_name = "HDR", required_if_eq("sky", "map"))]
+    environment_map: Option<PathBuf>,
+
+    /// Scale applied to the environment map
+    #[arg(long, default_value_t = 1.)]
+    environment_intensity: f32,
+
+    /// `.hdr` or 8 bit `.ppm` image repeated over the floor of the basic
+    /// and principled scenes, one unit across
+    #[arg(long, value_name = "IMAGE")]
+    floor_texture: Option<PathBuf>,
+
+    /// Haziness of the physical sky, from 2 (clear) to 10 (hazy)
+    #[arg(long, default_value_t = 3.)]
+    turbidity: f32,
+
+    /// Distance within which surfaces occlude each other in ambient
+    /// occlusion mode
+    #[arg(long, default_value_t = 1.)]
+    ao_distance: f32,
+
+    /// Number of occlusion rays per camera ray in ambient occlusion mode
+    #[arg(long, default_value_t = 16)]
+    ao_samples: usize,
+
+    /// Number of photons shot per pass by the photon mapper
+    #[arg(long, default_value_t = 200_000)]
+    photons: usize,
+
+    /// Number of progressive photon mapping passes
+    #[arg(long, default_value_t = 1)]
+    photon_passes: usize,
+
+    /// Radius photons are gathered from in the first pass. Picked from the
+    /// photon density when not given.
+    #[arg(long)]
+    photon_radius: Option<f32>,
 }
 
-/// Basic world configuration used in the ray tracing in a weekend book
-#[allow(dead_code)]
-fn make_basic_world() -> HittableList {
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SceneKind {
+    /// Final scene of the first book
+    Random,
+    /// Cornell box lit by a ceiling light
+    Cornell,
+    /// Three spheres on the ground, one of them hollow glass
+    Basic,
+    /// A flint glass prism, gemstones and a drop of water in front of
+    /// colored spheres
+    Prism,
+    /// Tinted and frosted glass, and a window pane
+    Glass,
+    /// Rough and polished metals on concrete under an area light
+    Metals,
+    /// Plastic, metal, cloth, glass and a glowing ball of the principled
+    /// material on a checkered floor
+    Principled,
+    /// Car paint, varnished wood and lacquered metals under an area light
+    Coated,
+    /// Soap bubbles over an oil slick, and tarnished silver
+    ThinFilm,
+    /// Marble, wax, skin and jade, lit through their surface
+    Subsurface,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Mode {
+    /// Path tracing that samples the lights at every diffuse bounce
+    Nee,
+    /// Path tracing that only finds lights by chance
+    Path,
+    /// Path tracing that scatters towards an even mix of the lights and the
+    /// material
+    Mixture,
+    /// Bidirectional path tracing, connecting camera and light subpaths
+    Bdpt,
+    /// Path tracing with caustics from a photon map
+    Photon,
+    /// Ambient occlusion of the first hit
+    Ao,
+    /// Shading normals of the first hit
+    Normals,
+    /// Distance from the camera to the first hit along its view direction
+    Depth,
+    /// Base color of the material at the first hit
+    Albedo,
+    /// A distinct color for every object
+    MaterialId,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum WorkingSpace {
+    /// The primaries of sRGB displays
+    LinearSrgb,
+    /// The wide gamut AP1 primaries of ACES
+    Acescg,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ToneMap {
+    /// Cut off everything brighter than white
+    Clamp,
+    Reinhard,
+    /// Reinhard reaching white at `--white-point`
+    ExtendedReinhard,
+    /// Filmic curve from Uncharted 2
+    Hable,
+    /// Fit of the ACES filmic transform
+    Aces,
+    /// AgX, desaturating highlights instead of shifting their hue
+    Agx,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum PngBitDepth {
+    #[value(name = "8")]
+    Eight,
+    #[value(name = "16")]
+    Sixteen,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ExrPixelType {
+    /// 16 bit floats
+    Half,
+    /// 32 bit floats
+    Float,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ExrCompression {
+    None,
+    /// Lossless zlib compression
+    Zip,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SkyKind {
+    /// Blue to white gradient from the book
+    Gradient,
+    /// Preetham daylight model with a sun
+    Preetham,
+    /// Panorama loaded from `--environment-map`
+    Map,
+}
+
+/// Builds the integrator requested on the command line. `bounds` encloses
+/// the objects of the scene, which is seen from `look_from` looking towards
+/// `look_to`.
+fn make_integrator(
+    args: &Args,
+    bounds: (Vec3, f32),
+    look_from: Vec3,
+    look_to: Vec3,
+) -> Box<dyn integrator::Integrator> {
+    let path_tracer = |light_sampling| -> Box<dyn integrator::Integrator> {
+        let path_tracer = Box::new(integrator::path::PathTracer {
+            max_depth: 50,
+            roulette_depth: args.roulette_depth,
+            light_sampling,
+        });
+        if args.spectral {
+            Box::new(integrator::spectral::Spectral { inner: path_tracer })
+        } else {
+            path_tracer
+        }
+    };
+    match args.mode {
+        Mode::Nee => path_tracer(integrator::path::LightSampling::Mis),
+        Mode::Path => path_tracer(integrator::path::LightSampling::None),
+        Mode::Mixture => path_tracer(integrator::path::LightSampling::Mixture),
+        Mode::Bdpt => Box::new(integrator::bdpt::Bdpt::default()),
+        Mode::Photon => {
+            let mut photon_mapper = integrator::photon::PhotonMapper::default();
+            photon_mapper.roulette_depth = args.roulette_depth;
+            photon_mapper.photons = args.photons;
+            photon_mapper.passes = args.photon_passes;
+            photon_mapper.radius = args.photon_radius;
+            photon_mapper.sun_target = Some(bounds);
+            Box::new(photon_mapper)
+        }
+        Mode::Ao => Box::new(integrator::debug::AmbientOcclusion {
+            distance: args.ao_distance,
+            samples: args.ao_samples,
+        }),
+        Mode::Normals => Box::new(integrator::debug::Normals),
+        Mode::Depth => {
+            let (center, radius) = bounds;
+            Box::new(integrator::debug::Depth {
+                forward: (look_to - look_from).normalize(),
+                far: (center - look_from).magnitude() + radius,
+            })
+        }
+        Mode::Albedo => Box::new(integrator::debug::Albedo),
+        Mode::MaterialId => Box::new(integrator::debug::MaterialId),
+    }
+}
+
+/// Builds the background requested on the command line
+fn make_background(args: &Args) -> Result<Box<dyn sky::Background>> {
+    Ok(match args.sky {
+        SkyKind::Gradient => Box::new(sky::Gradient::default()),
+        SkyKind::Preetham => {
+            let sun = sky::Sun::new(
+                sky::direction_from_angles(args.sun_elevation, args.sun_azimuth),
+                args.sun_diameter,
+                Color::new(1., 1., 1.) * args.sun_irradiance,
+            );
+            Box::new(sky::PreethamSky::new(
+                sun,
+                args.turbidity,
+                working_space(args),
+            ))
+        }
+        SkyKind::Map => {
+            let path = args
+                .environment_map
+                .as_ref()
+                .context("the map sky needs --environment-map")?;
+            let image = read_image(args, path)?;
+            Box::new(sky::EnvironmentMap::new(image, args.environment_intensity))
+        }
+    })
+}
+
+/// Reads a `.hdr` image, or a `.ppm` one decoded from the sRGB curve, into
+/// the working space
+fn read_image(args: &Args, path: &Path) -> Result<Image> {
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut input = BufReader::new(file);
+    let image = match path.extension().and_then(|e| e.to_str()) {
+        Some("ppm") => image::ppm::read(&mut input),
+        _ => image::hdr::read(&mut input),
+    };
+    let mut image = image.with_context(|| format!("reading {}", path.display()))?;
+    // Both hold sRGB colors, like nearly every image file
+    for pixel in &mut image.pixels {
+        *pixel = working_space(args).convert_from_srgb(pixel);
+    }
+    Ok(image)
+}
+
+/// The image given with `--floor-texture`, laid over the floor the scenes
+/// share at a height of -0.5
+fn floor_texture(args: &Args) -> Result<Option<texture::ImageTexture>> {
+    let Some(path) = &args.floor_texture else {
+        return Ok(None);
+    };
+    Ok(Some(texture::ImageTexture::new(
+        read_image(args, path)?,
+        Vec3(0., -0.5, 0.),
+        Vec3(1., 0., 0.),
+        Vec3(0., 0., 1.),
+    )))
+}
+
+/// Sphere enclosing the objects of each scene
+fn scene_bounds(scene: SceneKind) -> (Vec3, f32) {
+    match scene {
+        SceneKind::Random => (Vec3(0., 0., 0.), 16.),
+        SceneKind::Cornell => (Vec3(278., 278., 278.), 480.),
+        SceneKind::Basic => (Vec3(0., -0.25, -1.), 2.),
+        SceneKind::Prism => (Vec3(0., 0., -2.), 3.),
+        SceneKind::Glass => (Vec3(0., 0., -1.8), 2.5),
+        // The material showcases all put a row of balls on a floor
+        SceneKind::Metals
+        | SceneKind::Principled
+        | SceneKind::Coated
+        | SceneKind::ThinFilm
+        | SceneKind::Subsurface => (Vec3(0., 0., -1.), 3.),
+    }
+}
+
+/// Frames the row of balls the material showcases put on a floor
+fn showcase_camera(builder: &mut camera::CameraBuilder, args: &Args) -> Result<()> {
+    builder.aspect_ratio = 16. / 9.;
+    builder.image_width = 400;
+    builder.samples_per_pixel = 100;
+    builder.vfov = 35.;
+    builder.look_from = Vec3(0., 1., 3.);
+    builder.look_to = Vec3(0., 0., -1.);
+    builder.defocus_angle = 0.;
+    builder.background = make_background(args)?;
+    Ok(())
+}
+
+/// Basic world configuration used in the ray tracing in a weekend book. The
+/// ground is plain unless `floor` is given.
+fn make_basic_world(floor: Option<texture::ImageTexture>) -> HittableList {
     let mut world = HittableList::default();
 
     // Materials
-    let material_ground = material::Lambertian::new(color::Color::new(0.8, 0.8, 0.));
+    let material_ground = match floor {
+        Some(floor) => material::Lambertian::textured(floor),
+        None => material::Lambertian::new(color::Color::new(0.8, 0.8, 0.)),
+    };
     let material_center = material::Lambertian::new(color::Color::new(0.1, 0.2, 0.5));
     let material_glass = material::Dielectric::new(1.5);
     let air_pocket = material::Dielectric::new(1. / 1.5);
@@ -58,6 +444,415 @@ fn make_basic_world() -> HittableList {
     world
 }
 
+/// Glass that splits light into its colors, seen against spheres behind it
+fn make_prism_world() -> HittableList {
+    let mut world = HittableList::default();
+
+    world.push(Sphere::new(
+        Vec3(0., -100.5, -1.),
+        100.,
+        Lambertian::new(Color::new(0.8, 0.8, 0.8)),
+    ));
+    for (i, albedo) in [
+        Color::new(0.8, 0.1, 0.1),
+        Color::new(0.9, 0.9, 0.9),
+        Color::new(0.1, 0.2, 0.8),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        world.push(Sphere::new(
+            Vec3(i as f32 - 1., -0.1, -3.5),
+            0.4,
+            Lambertian::new(albedo),
+        ));
+    }
+
+    // Lying on one of its sides with the edges along x, so it bends the
+    // view of the spheres up and down
+    let glass = || material::Dispersive::new(material::Dispersion::FLINT);
+    let (left, right) = (-0.8, 0.8);
+    let front = |x| Vec3(x, -0.498, -1.);
+    let back = |x| Vec3(x, -0.498, -1.8);
+    let apex = |x| Vec3(x, 0.19, -1.4);
+    let length = Vec3(right - left, 0., 0.);
+    world.push(Quad::new(
+        front(left),
+        length,
+        apex(left) - front(left),
+        glass(),
+    ));
+    world.push(Quad::new(
+        back(left),
+        apex(left) - back(left),
+        length,
+        glass(),
+    ));
+    world.push(Quad::new(
+        front(left),
+        back(left) - front(left),
+        length,
+        glass(),
+    ));
+    world.push(Triangle::new(front(left), apex(left), back(left), glass()));
+    world.push(Triangle::new(
+        front(right),
+        back(right),
+        apex(right),
+        glass(),
+    ));
+
+    world.push(Sphere::new(
+        Vec3(-1.1, -0.3, -0.5),
+        0.2,
+        material::Dispersive::new(material::Dispersion::DIAMOND),
+    ));
+    world.push(Sphere::new(
+        Vec3(1.1, -0.3, -0.5),
+        0.2,
+        material::Dispersive::new(material::Dispersion::BK7),
+    ));
+    world.push(Sphere::new(
+        Vec3(0., -0.35, -0.4),
+        0.15,
+        material::Dispersive::new(material::Dispersion::WATER),
+    ));
+
+    world
+}
+
+/// Tinted glass, which gets darker the farther light goes through it,
+/// frosted glass, and a thin window in front of diffuse spheres
+fn make_glass_world() -> HittableList {
+    let mut world = HittableList::default();
+
+    world.push(Sphere::new(
+        Vec3(0., -100.5, -1.),
+        100.,
+        Lambertian::new(Color::new(0.8, 0.8, 0.8)),
+    ));
+
+    // The same blue glass in a small and a large ball
+    let blue = || material::Dielectric::tinted(1.5, Color::new(0.3, 0.5, 0.9), 0.4);
+    world.push(Sphere::new(Vec3(-1.2, -0.3, -1.), 0.2, blue()));
+    world.push(Sphere::new(Vec3(-0.5, 0., -1.3), 0.5, blue()));
+    world.push(Sphere::new(
+        Vec3(0.4, -0.2, -0.7),
+        0.3,
+        material::Dielectric::tinted(1.5, Color::new(0.9, 0.5, 0.1), 0.3),
+    ));
+    world.push(Sphere::new(
+        Vec3(1.3, -0.25, -0.9),
+        0.25,
+        material::rough_dielectric::RoughDielectric::new(1.5, material::microfacet::Ggx::new(0.3)),
+    ));
+
+    world.push(Quad::new(
+        Vec3(0.2, -0.5, -1.8),
+        Vec3(1.4, 0., -0.5),
+        Vec3(0., 1.2, 0.),
+        // Window glass with its slight green. Windows are often seen at grazing
+        // angles, where Schlick's approximation is furthest off.
+        material::Dielectric::tinted(1.5, Color::new(0.8, 0.95, 0.85), 0.01)
+            .thin_walled(0.01)
+            .with_fresnel(material::Fresnel::Exact),
+    ));
+    world.push(Sphere::new(
+        Vec3(0.7, -0.1, -2.6),
+        0.4,
+        Lambertian::new(Color::new(0.8, 0.1, 0.1)),
+    ));
+    world.push(Sphere::new(
+        Vec3(1.6, -0.2, -2.3),
+        0.3,
+        material::Metal::new(Color::new(0.8, 0.8, 0.8), 0.),
+    ));
+
+    world
+}
+
+/// Gold, silver, copper and aluminium, from polished to rough and brushed,
+/// on rough concrete. The light above is returned as the light list.
+fn make_metals_world() -> (HittableList, HittableList) {
+    use material::{
+        conductor::{ComplexIor, Conductor},
+        microfacet::Ggx,
+        oren_nayar::OrenNayar,
+    };
+
+    let mut world = HittableList::default();
+    let mut lights = HittableList::default();
+
+    // Concrete
+    world.push(Sphere::new(
+        Vec3(0., -100.5, -1.),
+        100.,
+        OrenNayar::new(Color::new(0.5, 0.5, 0.5), 0.6),
+    ));
+    let metals = [
+        (ComplexIor::GOLD, Ggx::new(0.)),
+        (ComplexIor::SILVER, Ggx::new(0.2)),
+        (ComplexIor::COPPER, Ggx::new(0.4)),
+    ];
+    for (i, (ior, distribution)) in metals.into_iter().enumerate() {
+        world.push(Sphere::new(
+            Vec3(1.1 * i as f32 - 1.65, 0., -1.),
+            0.5,
+            Conductor::new(ior, distribution),
+        ));
+    }
+    // Brushed around its vertical axis
+    let center = Vec3(1.65, 0., -1.);
+    world.push(Sphere::new(
+        center,
+        0.5,
+        Conductor::new(ComplexIor::ALUMINIUM, Ggx::anisotropic(0.4, 0.9))
+            .with_tangent(move |p: &Vec3| Vec3(center.2 - p.2, 0., p.0 - center.0)),
+    ));
+
+    let light = Arc::new(Quad::new(
+        Vec3(-1., 2.5, -1.5),
+        Vec3(2., 0., 0.),
+        Vec3(0., 0., 1.),
+        material::DiffuseLight::new(Color::new(4., 4., 4.)),
+    ));
+    world.push(light.clone());
+    lights.push(light);
+
+    (world, lights)
+}
+
+/// Balls showing off the principled material. The floor is a checker
+/// unless `floor` is given.
+fn make_principled_world(floor: Option<texture::ImageTexture>) -> (HittableList, HittableList) {
+    use material::principled::Principled;
+    use texture::{Checker, Texture};
+
+    let mut world = HittableList::default();
+    let mut lights = HittableList::default();
+
+    world.push(Sphere::new(
+        Vec3(0., -100.5, -1.),
+        100.,
+        Principled {
+            base_color: match floor {
+                Some(floor) => Box::new(floor) as Box<dyn Texture<Color>>,
+                None => Box::new(Checker {
+                    even: Color::new(0.8, 0.8, 0.8),
+                    odd: Color::new(0.2, 0.3, 0.1),
+                    size: 0.5,
+                }),
+            },
+            ..Default::default()
+        },
+    ));
+    let balls = [
+        Principled {
+            base_color: Box::new(Color::new(0.7, 0.1, 0.1)),
+            roughness: Box::new(0.6),
+            clearcoat: Box::new(1.),
+            ..Default::default()
+        },
+        Principled {
+            base_color: Box::new(Color::new(0.9, 0.7, 0.3)),
+            metallic: Box::new(1.),
+            roughness: Box::new(0.3),
+            ..Default::default()
+        },
+        Principled {
+            base_color: Box::new(Color::new(0.1, 0.1, 0.4)),
+            roughness: Box::new(1.),
+            sheen: Box::new(1.),
+            ..Default::default()
+        },
+        Principled {
+            base_color: Box::new(Color::new(0.9, 0.95, 1.)),
+            roughness: Box::new(0.1),
+            transmission: Box::new(1.),
+            ..Default::default()
+        },
+    ];
+    for (i, ball) in balls.into_iter().enumerate() {
+        world.push(Sphere::new(Vec3(1.1 * i as f32 - 1.65, 0., -1.), 0.5, ball));
+    }
+
+    let glow = Arc::new(Sphere::new(
+        Vec3(0., 1.6, -1.5),
+        0.3,
+        Principled {
+            base_color: Box::new(Color::black()),
+            emission: Box::new(Color::new(12., 10., 8.)),
+            ..Default::default()
+        },
+    ));
+    world.push(glow.clone());
+    lights.push(glow);
+
+    (world, lights)
+}
+
+/// Car paint, varnished wood and lacquered metals, each under a clearcoat.
+/// The light above is returned as the light list.
+fn make_coated_world() -> (HittableList, HittableList) {
+    use material::{
+        coated::Coated,
+        conductor::{ComplexIor, Conductor},
+        microfacet::Ggx,
+    };
+
+    let mut world = HittableList::default();
+    let mut lights = HittableList::default();
+
+    world.push(Sphere::new(
+        Vec3(0., -100.5, -1.),
+        100.,
+        Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+    ));
+    let center = |i: f32| Vec3(1.1 * i - 1.65, 0., -1.);
+    let car_paint = Coated::new(
+        Lambertian::new(Color::new(0.6, 0.02, 0.02)),
+        1.5,
+        0.05,
+        Ggx::new(0.),
+    );
+    world.push(Sphere::new(center(0.), 0.5, car_paint));
+    let varnished_wood = Coated::new(
+        Lambertian::new(Color::new(0.5, 0.3, 0.15)),
+        1.5,
+        0.2,
+        Ggx::new(0.2),
+    )
+    .tinted(Color::new(0.9, 0.7, 0.4), 0.2);
+    world.push(Sphere::new(center(1.), 0.5, varnished_wood));
+    let lacquered_metal = Coated::new(
+        material::Metal::new(Color::new(0.3, 0.4, 0.8), 0.3),
+        1.5,
+        0.05,
+        Ggx::new(0.),
+    );
+    world.push(Sphere::new(center(2.), 0.5, lacquered_metal));
+    let lacquered_gold = Coated::new(
+        Conductor::new(ComplexIor::GOLD, Ggx::new(0.5)),
+        1.5,
+        0.05,
+        Ggx::new(0.),
+    );
+    world.push(Sphere::new(center(3.), 0.5, lacquered_gold));
+
+    let light = Arc::new(Quad::new(
+        Vec3(-1., 2.5, -1.5),
+        Vec3(2., 0., 0.),
+        Vec3(0., 0., 1.),
+        material::DiffuseLight::new(Color::new(4., 4., 4.)),
+    ));
+    world.push(light.clone());
+    lights.push(light);
+
+    (world, lights)
+}
+
+/// Soap bubbles and an oil slick on a puddle, whose colors come from
+/// interference in their films, next to tarnished silver
+fn make_thin_film_world() -> HittableList {
+    use material::{
+        conductor::{ComplexIor, Conductor},
+        microfacet::Ggx,
+        thin_film::ThinFilm,
+        Dielectric,
+    };
+    use vec3::Point3;
+
+    let mut world = HittableList::default();
+
+    // A puddle over dark ground, with oil spreading unevenly on top
+    let oil = ThinFilm {
+        refractive_index: 1.45,
+        thickness: Box::new(|p: &Point3| {
+            400. + 150. * (3. * p.0).sin() * (4. * p.2 + 2. * p.0).cos()
+        }),
+    };
+    world.push(Sphere::new(
+        Vec3(0., -100.5, -1.),
+        100.,
+        Dielectric::new(1.33).with_thin_film(oil),
+    ));
+    world.push(Sphere::new(
+        Vec3(0., -100.5, -1.),
+        99.9,
+        Lambertian::new(Color::new(0.01, 0.01, 0.01)),
+    ));
+
+    // Soap drains down, leaving the top of bubbles thinner
+    for (center, radius) in [(Vec3(-1., 0.1, -1.), 0.5), (Vec3(0.1, 0.3, -1.6), 0.4)] {
+        let soap = ThinFilm {
+            refractive_index: 1.33,
+            thickness: Box::new(move |p: &Point3| 500. - 300. * (p.1 - center.1) / radius),
+        };
+        world.push(Sphere::new(
+            center,
+            radius,
+            Dielectric::new(1.).with_thin_film(soap),
+        ));
+    }
+
+    // A layer of tarnish, thicker towards the top
+    let oxide = ThinFilm {
+        refractive_index: 2.6,
+        thickness: Box::new(|p: &Point3| 90. + 80. * p.1),
+    };
+    world.push(Sphere::new(
+        Vec3(1.1, 0., -1.),
+        0.5,
+        Conductor::new(ComplexIor::SILVER, Ggx::new(0.1)).with_thin_film(oxide),
+    ));
+
+    world
+}
+
+/// Marble, wax, skin and jade, which light enters and leaves somewhere
+/// else. The light above is returned as the light list.
+fn make_subsurface_world() -> (HittableList, HittableList) {
+    use material::subsurface::Subsurface;
+
+    let mut world = HittableList::default();
+    let mut lights = HittableList::default();
+
+    world.push(Sphere::new(
+        Vec3(0., -100.5, -1.),
+        100.,
+        Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+    ));
+    // Albedo and mean free path of each channel
+    let media = [
+        // Marble
+        (Color::new(0.99, 0.99, 0.98), Color::new(0.1, 0.1, 0.1)),
+        // Wax
+        (Color::new(0.98, 0.9, 0.6), Color::new(0.15, 0.12, 0.1)),
+        // Skin, where red light goes the deepest
+        (Color::new(0.98, 0.85, 0.75), Color::new(0.3, 0.12, 0.08)),
+        // Jade
+        (Color::new(0.8, 0.97, 0.85), Color::new(0.2, 0.2, 0.2)),
+    ];
+    for (i, (albedo, mean_free_path)) in media.into_iter().enumerate() {
+        world.push(Sphere::new(
+            Vec3(1.1 * i as f32 - 1.65, 0., -1.),
+            0.5,
+            Subsurface::from_mean_free_path(1.4, albedo, mean_free_path),
+        ));
+    }
+
+    let light = Arc::new(Quad::new(
+        Vec3(-1., 2.5, -1.5),
+        Vec3(2., 0., 0.),
+        Vec3(0., 0., 1.),
+        material::DiffuseLight::new(Color::new(4., 4., 4.)),
+    ));
+    world.push(light.clone());
+    lights.push(light);
+
+    (world, lights)
+}
+
 #[allow(dead_code)]
 fn make_wide_angle_world() -> HittableList {
     let mut world = HittableList::default();
@@ -139,32 +934,290 @@ fn make_random_world() -> hittable::HittableList {
     world
 }
 
+/// The classic Cornell box. The ceiling light is shared between the world
+/// and the returned light list.
+fn make_cornell_box() -> (HittableList, HittableList) {
+    let (mut world, lights) = make_empty_cornell_box();
+
+    world.push(Sphere::new(
+        Vec3(190., 90., 190.),
+        90.,
+        material::Dielectric::new(1.5),
+    ));
+    world.push(Sphere::new(
+        Vec3(370., 120., 370.),
+        120.,
+        Lambertian::new(Color::new(0.73, 0.73, 0.73)),
+    ));
+
+    (world, lights)
+}
+
+/// Walls and ceiling light of the Cornell box, without anything inside
+fn make_empty_cornell_box() -> (HittableList, HittableList) {
+    let mut world = HittableList::default();
+    let mut lights = HittableList::default();
+
+    let red = || Lambertian::new(Color::new(0.65, 0.05, 0.05));
+    let white = || Lambertian::new(Color::new(0.73, 0.73, 0.73));
+    let green = || Lambertian::new(Color::new(0.12, 0.45, 0.15));
+
+    world.push(Quad::new(
+        Vec3(555., 0., 0.),
+        Vec3(0., 555., 0.),
+        Vec3(0., 0., 555.),
+        green(),
+    ));
+    world.push(Quad::new(
+        Vec3(0., 0., 0.),
+        Vec3(0., 555., 0.),
+        Vec3(0., 0., 555.),
+        red(),
+    ));
+    world.push(Quad::new(
+        Vec3(0., 0., 0.),
+        Vec3(555., 0., 0.),
+        Vec3(0., 0., 555.),
+        white(),
+    ));
+    world.push(Quad::new(
+        Vec3(555., 555., 555.),
+        Vec3(-555., 0., 0.),
+        Vec3(0., 0., -555.),
+        white(),
+    ));
+    world.push(Quad::new(
+        Vec3(0., 0., 555.),
+        Vec3(555., 0., 0.),
+        Vec3(0., 555., 0.),
+        white(),
+    ));
+
+    // Facing down into the box
+    let light = Arc::new(Quad::new(
+        Vec3(213., 554., 227.),
+        Vec3(130., 0., 0.),
+        Vec3(0., 0., 105.),
+        material::DiffuseLight::new(Color::new(15., 15., 15.)),
+    ));
+    world.push(light.clone());
+    lights.push(light);
+
+    (world, lights)
+}
+
 fn run(args: &Args) -> Result<()> {
     // Configure camera
     // TODO: Move this logic out to its own function
     let mut camera_builder = camera::Camera::builder();
-    camera_builder.aspect_ratio = 5. / 4.;
-    camera_builder.image_width = 1200;
-    camera_builder.samples_per_pixel = 500;
-    camera_builder.reflection_depth = 50;
-    camera_builder.vfov = 20.;
-    camera_builder.look_from = Vec3(13., 2., 3.);
-    camera_builder.look_to = Vec3(0., 0., 0.);
     camera_builder.vup = Vec3(0., 1., 0.);
-    camera_builder.defocus_angle = 0.6;
-    camera_builder.focus_distance = 10.;
+    camera_builder.aovs = args.aovs;
+    camera_builder.alpha = args.alpha;
+    // Other formats would get a black background with nothing to tell it
+    // apart
+    let extension = args.output.as_ref().and_then(|path| path.extension());
+    if args.alpha && !matches!(extension.and_then(|e| e.to_str()), Some("png" | "exr")) {
+        bail!("--alpha needs PNG or EXR output");
+    }
 
     // World
-    let world = make_random_world();
+    let (world, lights) = match args.scene {
+        SceneKind::Random => {
+            camera_builder.aspect_ratio = 5. / 4.;
+            camera_builder.image_width = 1200;
+            camera_builder.samples_per_pixel = 500;
+            camera_builder.vfov = 20.;
+            camera_builder.look_from = Vec3(13., 2., 3.);
+            camera_builder.look_to = Vec3(0., 0., 0.);
+            camera_builder.defocus_angle = 0.6;
+            camera_builder.focus_distance = 10.;
+            camera_builder.background = make_background(args)?;
+            (make_random_world(), HittableList::default())
+        }
+        SceneKind::Cornell => {
+            camera_builder.aspect_ratio = 1.;
+            camera_builder.image_width = 600;
+            camera_builder.samples_per_pixel = 100;
+            camera_builder.vfov = 40.;
+            camera_builder.look_from = Vec3(278., 278., -800.);
+            camera_builder.look_to = Vec3(278., 278., 0.);
+            camera_builder.defocus_angle = 0.;
+            camera_builder.background =
+                Box::new(sky::Gradient::new(Color::black(), Color::black()));
+            make_cornell_box()
+        }
+        SceneKind::Basic => {
+            camera_builder.aspect_ratio = 16. / 9.;
+            camera_builder.image_width = 400;
+            camera_builder.samples_per_pixel = 100;
+            camera_builder.vfov = 35.;
+            camera_builder.look_from = Vec3(-2., 2., 1.);
+            camera_builder.look_to = Vec3(0., 0., -1.);
+            camera_builder.defocus_angle = 0.;
+            camera_builder.background = make_background(args)?;
+            (
+                make_basic_world(floor_texture(args)?),
+                HittableList::default(),
+            )
+        }
+        SceneKind::Prism => {
+            camera_builder.aspect_ratio = 16. / 9.;
+            camera_builder.image_width = 400;
+            camera_builder.samples_per_pixel = 100;
+            camera_builder.vfov = 35.;
+            camera_builder.look_from = Vec3(0., 0.5, 1.8);
+            camera_builder.look_to = Vec3(0., -0.2, -1.5);
+            camera_builder.defocus_angle = 0.;
+            camera_builder.background = make_background(args)?;
+            (make_prism_world(), HittableList::default())
+        }
+        SceneKind::Glass => {
+            camera_builder.aspect_ratio = 16. / 9.;
+            camera_builder.image_width = 400;
+            camera_builder.samples_per_pixel = 100;
+            camera_builder.vfov = 40.;
+            camera_builder.look_from = Vec3(-0.3, 0.8, 1.5);
+            camera_builder.look_to = Vec3(0.2, -0.2, -1.5);
+            camera_builder.defocus_angle = 0.;
+            camera_builder.background = make_background(args)?;
+            (make_glass_world(), HittableList::default())
+        }
+        SceneKind::Metals => {
+            showcase_camera(&mut camera_builder, args)?;
+            make_metals_world()
+        }
+        SceneKind::Principled => {
+            showcase_camera(&mut camera_builder, args)?;
+            make_principled_world(floor_texture(args)?)
+        }
+        SceneKind::Coated => {
+            showcase_camera(&mut camera_builder, args)?;
+            make_coated_world()
+        }
+        SceneKind::ThinFilm => {
+            showcase_camera(&mut camera_builder, args)?;
+            (make_thin_film_world(), HittableList::default())
+        }
+        SceneKind::Subsurface => {
+            showcase_camera(&mut camera_builder, args)?;
+            make_subsurface_world()
+        }
+    };
+    if args.spectral {
+        if !matches!(args.mode, Mode::Nee | Mode::Path | Mode::Mixture) {
+            bail!("--spectral needs one of the nee, path or mixture modes");
+        }
+        // Colors are upsampled into spectra as sRGB
+        if !matches!(args.working_space, WorkingSpace::LinearSrgb) {
+            bail!("--spectral needs the linear-srgb working space");
+        }
+    }
+    camera_builder.integrator = make_integrator(
+        args,
+        scene_bounds(args.scene),
+        camera_builder.look_from,
+        camera_builder.look_to,
+    );
     let mut camera = camera_builder.build();
 
+    let (image, aovs) = camera.render(&world, &lights);
     match &args.output {
-        None => {
-            camera.render(&world);
-            Ok(())
+        None => image::ppm::write(
+            &mut std::io::stdout().lock(),
+            &make_tone_mapper(args).apply(&image),
+        )?,
+        Some(path) => write_output(args, path, &image, aovs.as_ref())?,
+    }
+    Ok(())
+}
+
+fn working_space(args: &Args) -> color::ColorSpace {
+    match args.working_space {
+        WorkingSpace::LinearSrgb => color::ColorSpace::LinearSrgb,
+        WorkingSpace::Acescg => color::ColorSpace::AcesCg,
+    }
+}
+
+/// Tone mapping of the 8 and 16 bit outputs, as requested on the command
+/// line
+fn make_tone_mapper(args: &Args) -> tonemap::ToneMapper {
+    let operator = match args.tone_map {
+        ToneMap::Clamp => tonemap::Operator::Clamp,
+        ToneMap::Reinhard => tonemap::Operator::Reinhard,
+        ToneMap::ExtendedReinhard => tonemap::Operator::ExtendedReinhard,
+        ToneMap::Hable => tonemap::Operator::Hable,
+        ToneMap::Aces => tonemap::Operator::Aces,
+        ToneMap::Agx => tonemap::Operator::Agx,
+    };
+    tonemap::ToneMapper {
+        operator,
+        working_space: working_space(args),
+        exposure: args.exposure,
+        white_point: args.white_point,
+    }
+}
+
+/// Writes the render to `path`, in the format its extension names. EXR
+/// files hold the auxiliary images as extra layers, while the other
+/// formats get PFM files next to them. Only PNG and PPM are tone mapped.
+fn write_output(args: &Args, path: &Path, image: &Image, aovs: Option<&Aovs>) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("exr") => {
+            let mut channels = image::exr::rgb_channels("", &image.pixels);
+            if let Some(alpha) = &image.alpha {
+                channels.push(image::exr::Channel {
+                    name: "A".to_string(),
+                    values: alpha.clone(),
+                });
+            }
+            if let Some(aovs) = aovs {
+                channels.extend(aovs.exr_channels());
+            }
+            let pixel_type = match args.exr_pixel_type {
+                ExrPixelType::Half => image::exr::PixelType::Half,
+                ExrPixelType::Float => image::exr::PixelType::Float,
+            };
+            let compression = match args.exr_compression {
+                ExrCompression::None => image::exr::Compression::None,
+                ExrCompression::Zip => image::exr::Compression::Zip,
+            };
+            image::exr::write(
+                &mut out,
+                image.width,
+                image.height,
+                &channels,
+                &working_space(args).chromaticities(),
+                pixel_type,
+                compression,
+            )?;
+        }
+        extension => {
+            match extension {
+                Some("hdr") => {
+                    let mut image = image.clone();
+                    for pixel in &mut image.pixels {
+                        *pixel = working_space(args).convert_to_srgb(pixel);
+                    }
+                    image::hdr::write(&mut out, &image)?
+                }
+                Some("png") => {
+                    let bit_depth = match args.png_bit_depth {
+                        PngBitDepth::Eight => image::png::BitDepth::Eight,
+                        PngBitDepth::Sixteen => image::png::BitDepth::Sixteen,
+                    };
+                    let image = make_tone_mapper(args).apply(image);
+                    image::png::write(&mut out, &image, bit_depth)?
+                }
+                _ => image::ppm::write(&mut out, &make_tone_mapper(args).apply(image))?,
+            }
+            if let Some(aovs) = aovs {
+                aovs.write_pfm(path)?;
+            }
         }
-        Some(x) => Err(anyhow!("Not implemented yet")),
     }
+    out.flush()?;
+    Ok(())
 }
 
 fn main() {
@@ -0,0 +1,240 @@
+//! A clear layer over another material, like the clearcoat of car paint or
+//! varnish on wood
+
+use super::{
+    absorption_coefficient,
+    microfacet::{Ggx, ReflectionPdf},
+    Fresnel, Lobes, Material, ScatterRecord,
+};
+use crate::{color::Color, hittable, pdf::Pdf, ray::Ray, vec3::onb::Onb};
+use rand::Rng;
+
+/// Roughness a smoother coat is raised to, since it is sampled along with
+/// the base and cannot be a perfect mirror
+const MIN_ROUGHNESS: f32 = 0.05;
+
+/// A dielectric coat over the front of a `base` material. Light is either
+/// reflected by the rough surface of the coat or goes through it to the
+/// base, and light the base sends back up may be reflected down again by
+/// the coat. Those bounces are summed as if the base were diffuse, or a
+/// mirror when it scatters specularly. Light the base lets through is lost.
+pub struct Coated<M: Material> {
+    base: M,
+    refractive_index: f32,
+    thickness: f32,
+    distribution: Ggx,
+    /// Fraction of light absorbed per unit of distance traveled in the coat
+    absorption: Color,
+    /// Share of the light going up through the coat it reflects back down,
+    /// averaged over diffuse directions
+    internal_reflectance: f32,
+}
+
+impl<M: Material> Coated<M> {
+    pub fn new(base: M, refractive_index: f32, thickness: f32, distribution: Ggx) -> Self {
+        let smoothest = Ggx::new(MIN_ROUGHNESS);
+        // Averaged reflectance from outside. Of diffuse light inside, only
+        // the cone refracted into the hemisphere outside can leave, which
+        // holds a share of one over the refractive index squared.
+        let steps = 256;
+        let external_reflectance: f32 = (0..steps)
+            .map(|i| {
+                let cosine = (i as f32 + 0.5) / steps as f32;
+                2. * cosine * Fresnel::Exact.reflectance(cosine, refractive_index) / steps as f32
+            })
+            .sum();
+        Coated {
+            base,
+            refractive_index,
+            thickness,
+            distribution: Ggx {
+                alpha_x: distribution.alpha_x.max(smoothest.alpha_x),
+                alpha_y: distribution.alpha_y.max(smoothest.alpha_y),
+            },
+            absorption: Color::black(),
+            internal_reflectance: 1.
+                - (1. - external_reflectance) / (refractive_index * refractive_index),
+        }
+    }
+
+    /// A coat that absorbs light following the Beer–Lambert law, so light
+    /// that went `distance` through it is left with `color`. Light crossing
+    /// the coat at an angle travels further through it.
+    pub fn tinted(self, color: Color, distance: f32) -> Self {
+        Coated {
+            absorption: absorption_coefficient(&color, distance),
+            ..self
+        }
+    }
+
+    /// Fraction of light reflected by the coat, arriving from outside at
+    /// `cosine` to the normal
+    fn reflectance(&self, cosine: f32) -> f32 {
+        Fresnel::Exact.reflectance(cosine, self.refractive_index)
+    }
+
+    /// Fraction of light left after crossing the coat once, entering or
+    /// leaving at `cosine` to the normal outside of it
+    fn transmittance(&self, absorption: &Color, cosine: f32) -> Color {
+        let sin_squared = (1. - cosine * cosine) / (self.refractive_index * self.refractive_index);
+        let distance = self.thickness / (1. - sin_squared).sqrt();
+        let channel = |i: usize| (-absorption[i] * distance).exp();
+        Color::new(channel(0), channel(1), channel(2))
+    }
+}
+
+impl<M: Material> Material for Coated<M> {
+    fn scatter(&self, r_in: &Ray, hit_rec: &hittable::HitRecord) -> Option<ScatterRecord> {
+        if !hit_rec.front_face {
+            return self.base.scatter(r_in, hit_rec);
+        }
+        let r_out = -r_in.direction;
+        let coat = ReflectionPdf::new(&hit_rec.normal, &r_out, self.distribution);
+        let reflectance = self.reflectance(r_out.normalize().dot(&hit_rec.normal));
+
+        match self.base.scatter(r_in, hit_rec)? {
+            ScatterRecord::Pdf(base) => Some(ScatterRecord::Pdf(Box::new(Lobes::new(vec![
+                (reflectance, Box::new(coat)),
+                (1. - reflectance, base),
+            ])))),
+            // Without a density for the base, either the coat or the base
+            // is followed, picked by how much light the coat reflects
+            ScatterRecord::Specular { attenuation, ray } => {
+                if rand::thread_rng().gen::<f32>() < reflectance {
+                    let scattered = r_in.spawn(coat.generate(), hit_rec.p);
+                    let pdf_value = coat.value(&scattered.direction);
+                    if pdf_value <= 0. {
+                        return None;
+                    }
+                    let frame = Onb::new(&hit_rec.normal);
+                    let wo = frame.local(&r_out.normalize());
+                    let wi = frame.local(&scattered.direction.normalize());
+                    let fresnel = self.reflectance(wo.dot(&(wo + wi).normalize()));
+                    let weight = self.distribution.reflection(&wo, &wi) * fresnel / pdf_value;
+                    return Some(ScatterRecord::Specular {
+                        attenuation: Color::new(1., 1., 1.) * (weight / reflectance),
+                        ray: scattered,
+                    });
+                }
+                let cos_out = ray.direction.normalize().dot(&hit_rec.normal);
+                if cos_out <= 0. {
+                    return None;
+                }
+                // Light the coat reflects back down hits the base at the
+                // same angle each time, and leaves along the same ray
+                let absorption = r_in.reflectance(&self.absorption);
+                let through = self.transmittance(&absorption, cos_out)
+                    * self.transmittance(&absorption, r_out.normalize().dot(&hit_rec.normal))
+                    * attenuation;
+                let exit_reflectance = self.reflectance(cos_out);
+                let channel = |i: usize| {
+                    (1. - exit_reflectance) * through[i] / (1. - exit_reflectance * through[i])
+                };
+                Some(ScatterRecord::Specular {
+                    attenuation: Color::new(channel(0), channel(1), channel(2)),
+                    ray,
+                })
+            }
+            // Walks go on under the coat, where its back face is hit
+            walk @ ScatterRecord::Walk { .. } => Some(walk),
+        }
+    }
+
+    fn eval(&self, r_in: &Ray, hit_rec: &hittable::HitRecord, scattered: &Ray) -> Color {
+        if !hit_rec.front_face {
+            return self.base.eval(r_in, hit_rec, scattered);
+        }
+        let frame = Onb::new(&hit_rec.normal);
+        let wo = frame.local(&-r_in.direction.normalize());
+        let wi = frame.local(&scattered.direction.normalize());
+        if wo.2 <= 0. || wi.2 <= 0. {
+            return Color::black();
+        }
+        let fresnel = self.reflectance(wo.dot(&(wo + wi).normalize()));
+        let coat = self.distribution.reflection(&wo, &wi) * fresnel;
+
+        // Radiance leaving the coat spreads over a wider solid angle than
+        // it had inside
+        let refracted = (1. - self.reflectance(wo.2)) * (1. - self.reflectance(wi.2))
+            / (self.refractive_index * self.refractive_index);
+        let absorption = r_in.reflectance(&self.absorption);
+        let through = self.transmittance(&absorption, wo.2) * self.transmittance(&absorption, wi.2);
+        // Bounces between the base and the coat, crossing the coat twice
+        // each time along twice its thickness on average
+        let albedo = r_in.reflectance(&self.base.albedo(hit_rec));
+        let bounces = |i: usize| {
+            let round_trip = (-4. * absorption[i] * self.thickness).exp();
+            1. / (1. - albedo[i] * round_trip * self.internal_reflectance)
+        };
+        Color::new(coat, coat, coat)
+            + self.base.eval(r_in, hit_rec, scattered)
+                * through
+                * Color::new(bounces(0), bounces(1), bounces(2))
+                * refracted
+    }
+
+    fn albedo(&self, hit_rec: &hittable::HitRecord) -> Color {
+        self.base.albedo(hit_rec)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        material::{Lambertian, Metal},
+        vec3::Vec3,
+    };
+
+    /// Light reflected towards `r_in`, estimated by sampling the material
+    fn reflected<M: Material>(material: &Coated<M>, r_in: &Ray) -> Color {
+        let hit_rec =
+            hittable::HitRecord::from_outside(Vec3(0., 0., 0.), Vec3(0., 0., 1.), material);
+        let n = 100000;
+        let mut total = Color::black();
+        for _ in 0..n {
+            match material.scatter(r_in, &hit_rec) {
+                Some(ScatterRecord::Pdf(pdf)) => {
+                    let scattered = Ray::new(pdf.generate(), hit_rec.p);
+                    let pdf_value = pdf.value(&scattered.direction);
+                    if pdf_value > 0. {
+                        total += material.eval(r_in, &hit_rec, &scattered) * (1. / pdf_value);
+                    }
+                }
+                Some(ScatterRecord::Specular { attenuation, .. })
+                | Some(ScatterRecord::Walk { attenuation, .. }) => total += attenuation,
+                None => (),
+            }
+        }
+        total * (1. / n as f32)
+    }
+
+    #[test]
+    fn clear_coat_keeps_energy() {
+        let r_in = Ray::new(Vec3(0.5, 0.2, -1.), Vec3(-0.5, -0.2, 1.));
+        let white = Color::new(1., 1., 1.);
+        // Everything the coat lets through comes back out eventually
+        let diffuse = Coated::new(Lambertian::new(white.clone()), 1.5, 0.1, Ggx::new(0.3));
+        let total = reflected(&diffuse, &r_in);
+        assert!(total[0] <= 1.01 && total[0] > 0.95, "{total:?}");
+        let mirror = Coated::new(Metal::new(white, 0.), 1.5, 0.1, Ggx::new(0.3));
+        let total = reflected(&mirror, &r_in);
+        assert!(total[0] <= 1.01 && total[0] > 0.95, "{total:?}");
+    }
+
+    #[test]
+    fn thicker_tinted_coats_are_darker() {
+        let r_in = Ray::new(Vec3(0., 0.2, -1.), Vec3(0., -0.2, 1.));
+        let base = || Lambertian::new(Color::new(0.8, 0.8, 0.8));
+        let color = Color::new(0.9, 0.6, 0.3);
+        let thin = Coated::new(base(), 1.5, 0.1, Ggx::new(0.2)).tinted(color.clone(), 1.);
+        let thick = Coated::new(base(), 1.5, 0.5, Ggx::new(0.2)).tinted(color, 1.);
+        let (thin, thick) = (reflected(&thin, &r_in), reflected(&thick, &r_in));
+        for i in 0..3 {
+            assert!(thick[i] < thin[i], "{thin:?} {thick:?}");
+        }
+        // Blue is absorbed the most, and only the coat reflects it
+        assert!(thick[2] < thick[1] && thick[1] < thick[0]);
+        assert!(thick[2] > 0.02);
+    }
+}
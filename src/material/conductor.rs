@@ -0,0 +1,250 @@
+//! Metals, reflecting off rough microfacets with the Fresnel equations of
+//! their complex refractive index
+
+use super::{
+    microfacet::{Ggx, ReflectionPdf},
+    thin_film::ThinFilm,
+    Material, ScatterRecord,
+};
+use crate::{
+    color::Color,
+    hittable,
+    ray::Ray,
+    spectrum::RGB_WAVELENGTHS,
+    texture::Texture,
+    vec3::{onb::Onb, Point3, Vec3},
+};
+
+/// Complex refractive index `eta + i k` of a metal at the wavelengths
+/// standing in for red, green and blue. `k` is how strongly light is
+/// absorbed as it enters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ComplexIor {
+    pub eta: [f32; 3],
+    pub k: [f32; 3],
+}
+
+impl ComplexIor {
+    pub const GOLD: ComplexIor = ComplexIor {
+        eta: [0.143, 0.374, 1.442],
+        k: [3.983, 2.385, 1.603],
+    };
+    pub const SILVER: ComplexIor = ComplexIor {
+        eta: [0.155, 0.117, 0.138],
+        k: [4.828, 3.122, 2.147],
+    };
+    pub const COPPER: ComplexIor = ComplexIor {
+        eta: [0.2, 0.924, 1.102],
+        k: [3.912, 2.452, 2.142],
+    };
+    pub const ALUMINIUM: ComplexIor = ComplexIor {
+        eta: [1.657, 0.88, 0.521],
+        k: [9.224, 6.27, 4.837],
+    };
+
+    /// Fraction of unpolarized light reflected when arriving at `cosine` to
+    /// the normal
+    pub fn reflectance(&self, cosine: f32) -> Color {
+        let cos_squared = cosine * cosine;
+        let sin_squared = 1. - cos_squared;
+        let channel = |i: usize| {
+            let (eta, k) = (self.eta[i], self.k[i]);
+            let t0 = eta * eta - k * k - sin_squared;
+            let a2_plus_b2 = (t0 * t0 + 4. * eta * eta * k * k).sqrt();
+            let a = ((a2_plus_b2 + t0) / 2.).max(0.).sqrt();
+            let t1 = a2_plus_b2 + cos_squared;
+            let t2 = 2. * cosine * a;
+            let perpendicular = (t1 - t2) / (t1 + t2);
+            let t3 = cos_squared * a2_plus_b2 + sin_squared * sin_squared;
+            let t4 = t2 * sin_squared;
+            let parallel = perpendicular * (t3 - t4) / (t3 + t4);
+            (perpendicular + parallel) / 2.
+        };
+        Color::new(channel(0), channel(1), channel(2))
+    }
+
+    /// Refractive index and absorption at `lambda` nanometers, interpolated
+    /// between the wavelengths of red, green and blue
+    pub fn at(&self, lambda: f32) -> (f32, f32) {
+        let [red, green, blue] = RGB_WAVELENGTHS;
+        let lerp = |a: usize, b: usize, t: f32| {
+            (
+                self.eta[a] + (self.eta[b] - self.eta[a]) * t,
+                self.k[a] + (self.k[b] - self.k[a]) * t,
+            )
+        };
+        if lambda >= green {
+            lerp(1, 0, ((lambda - green) / (red - green)).min(1.))
+        } else {
+            lerp(1, 2, ((green - lambda) / (green - blue)).min(1.))
+        }
+    }
+}
+
+/// Metal whose roughness follows a GGX distribution, sampled through the
+/// microfacets visible from the incoming ray. Unlike `Metal`, no energy is
+/// made up, and the color comes from the refractive index so it changes
+/// towards grazing angles like real metals.
+pub struct Conductor {
+    ior: ComplexIor,
+    distribution: Ggx,
+    film: Option<ThinFilm>,
+    /// Direction anisotropic roughness is stretched along
+    tangent: Option<Box<dyn Texture<Vec3>>>,
+}
+
+impl Conductor {
+    pub fn new(ior: ComplexIor, distribution: Ggx) -> Self {
+        Conductor {
+            ior,
+            distribution,
+            film: None,
+            tangent: None,
+        }
+    }
+
+    /// Stretches anisotropic roughness along `tangent`, so brushed metal
+    /// can follow the shape of the object. Otherwise the direction is
+    /// picked from the normal alone, and jumps around on curved surfaces.
+    pub fn with_tangent(self, tangent: impl Texture<Vec3> + 'static) -> Self {
+        Conductor {
+            tangent: Some(Box::new(tangent)),
+            ..self
+        }
+    }
+
+    /// Covers the metal with a film, like the oxide layer of heated steel
+    pub fn with_thin_film(self, film: ThinFilm) -> Self {
+        Conductor {
+            film: Some(film),
+            ..self
+        }
+    }
+
+    /// Fraction of light reflected at each wavelength of `r_in`
+    fn reflectance(&self, r_in: &Ray, p: &Point3, cosine: f32) -> Color {
+        match &self.film {
+            Some(film) => film.reflectance(r_in, p, cosine, 1., |lambda| self.ior.at(lambda)),
+            None => r_in.reflectance(&self.ior.reflectance(cosine)),
+        }
+    }
+
+    /// Local frame of the distribution at the hit
+    fn frame(&self, hit_rec: &hittable::HitRecord) -> Onb {
+        match &self.tangent {
+            Some(tangent) => Onb::with_tangent(&hit_rec.normal, &tangent.value(&hit_rec.p)),
+            None => Onb::new(&hit_rec.normal),
+        }
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, r_in: &Ray, hit_rec: &hittable::HitRecord) -> Option<ScatterRecord> {
+        if self.distribution.is_smooth() {
+            let unit_r_in_dir = r_in.direction.normalize();
+            let cosine = (-unit_r_in_dir).dot(&hit_rec.normal);
+            return Some(ScatterRecord::Specular {
+                attenuation: self.reflectance(r_in, &hit_rec.p, cosine),
+                ray: r_in.spawn(unit_r_in_dir.reflect(&hit_rec.normal), hit_rec.p),
+            });
+        }
+        Some(ScatterRecord::Pdf(Box::new(ReflectionPdf::in_frame(
+            self.frame(hit_rec),
+            &-r_in.direction,
+            self.distribution,
+        ))))
+    }
+
+    fn eval(&self, r_in: &Ray, hit_rec: &hittable::HitRecord, scattered: &Ray) -> Color {
+        if self.distribution.is_smooth() {
+            return Color::black();
+        }
+        let frame = self.frame(hit_rec);
+        let wo = frame.local(&-r_in.direction.normalize());
+        let wi = frame.local(&scattered.direction.normalize());
+        let reflection = self.distribution.reflection(&wo, &wi);
+        if reflection <= 0. {
+            return Color::black();
+        }
+        let m = (wo + wi).normalize();
+        self.reflectance(r_in, &hit_rec.p, wo.dot(&m)) * reflection
+    }
+
+    fn albedo(&self, _: &hittable::HitRecord) -> Color {
+        self.ior.reflectance(1.)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fresnel_of_complex_ior() {
+        for ior in [
+            ComplexIor::GOLD,
+            ComplexIor::SILVER,
+            ComplexIor::COPPER,
+            ComplexIor::ALUMINIUM,
+        ] {
+            let head_on = ior.reflectance(1.);
+            let grazing = ior.reflectance(0.);
+            for i in 0..3 {
+                let (eta, k) = (ior.eta[i], ior.k[i]);
+                let expected = ((eta - 1.).powi(2) + k * k) / ((eta + 1.).powi(2) + k * k);
+                assert!((head_on[i] - expected).abs() < 1e-5, "{ior:?}");
+                assert!((grazing[i] - 1.).abs() < 1e-5, "{ior:?}");
+            }
+        }
+        // Gold is yellow and silver nearly white
+        let gold = ComplexIor::GOLD.reflectance(1.);
+        assert!(gold[0] > gold[1] && gold[1] > gold[2]);
+        assert!(ComplexIor::SILVER.reflectance(1.)[2] > 0.9);
+    }
+
+    #[test]
+    fn rough_metal_reflects_at_most_its_fresnel() {
+        let metal = Conductor::new(ComplexIor::SILVER, Ggx::new(0.6));
+        let hit_rec = hittable::HitRecord::from_outside(Vec3(0., 0., 0.), Vec3(0., 0., 1.), &metal);
+        let r_in = Ray::new(Vec3(0.3, 0.1, -1.), Vec3(-0.3, -0.1, 1.));
+        let Some(ScatterRecord::Pdf(pdf)) = metal.scatter(&r_in, &hit_rec) else {
+            panic!("rough metal samples a pdf");
+        };
+
+        let n = 20000;
+        let mut reflected = Color::black();
+        for _ in 0..n {
+            let scattered = Ray::new(pdf.generate(), hit_rec.p);
+            let pdf_value = pdf.value(&scattered.direction);
+            reflected += metal.eval(&r_in, &hit_rec, &scattered) * (1. / pdf_value / n as f32);
+        }
+        // Light bouncing between microfacets more than once is lost, which
+        // outweighs the Fresnel term growing away from the normal
+        let fresnel = ComplexIor::SILVER.reflectance(1.);
+        for i in 0..3 {
+            assert!(reflected[i] < fresnel[i] && reflected[i] > 0.8 * fresnel[i]);
+        }
+    }
+
+    #[test]
+    fn brushed_metal_follows_its_tangent() {
+        let brushed = |tangent: Vec3| {
+            Conductor::new(ComplexIor::ALUMINIUM, Ggx::anisotropic(0.4, 0.9)).with_tangent(tangent)
+        };
+        let along_x = brushed(Vec3(1., 0., 0.));
+        let along_y = brushed(Vec3(0., 1., 0.));
+        let r_in = Ray::new(Vec3(0., 0., -1.), Vec3(0., 0., 1.));
+        let reflected = |metal: &Conductor, direction: Vec3| {
+            let hit_rec =
+                hittable::HitRecord::from_outside(Vec3(0., 0., 0.), Vec3(0., 0., 1.), metal);
+            metal.eval(&r_in, &hit_rec, &Ray::new(direction, hit_rec.p))[0]
+        };
+
+        // The highlight spreads further along the tangent
+        let tilted_x = Vec3(0.6, 0., 1.);
+        let tilted_y = Vec3(0., 0.6, 1.);
+        assert!(reflected(&along_x, tilted_x) > 2. * reflected(&along_x, tilted_y));
+        let swapped = reflected(&along_y, tilted_y);
+        assert!((swapped - reflected(&along_x, tilted_x)).abs() < 1e-5 * swapped);
+    }
+}
@@ -0,0 +1,129 @@
+//! Participating media, where light is scattered and absorbed on its way
+//! through rather than at a surface
+
+use super::Isotropic;
+use crate::{color::Color, pdf::Pdf, ray::Ray};
+use rand::Rng;
+
+/// A medium with the same density everywhere, whose particles scatter
+/// light like `Isotropic`
+pub struct Medium {
+    /// Chance per unit of distance of light being scattered
+    scattering: Color,
+    /// Chance per unit of distance of light being absorbed
+    absorption: Color,
+    /// What light scatters off, with the share of light scattered rather
+    /// than absorbed as its albedo
+    particles: Isotropic,
+}
+
+/// Where light going through a medium ends up
+pub enum Flight {
+    /// Scattered somewhere on the way, going on along `ray`
+    Scattered { attenuation: Color, ray: Ray },
+    /// Got all the way through. `ray` starts at the end, still heading the
+    /// same way.
+    Through { attenuation: Color, ray: Ray },
+}
+
+impl Medium {
+    pub fn new(scattering: Color, absorption: Color) -> Self {
+        let albedo = |i: usize| {
+            let extinction = scattering[i] + absorption[i];
+            if extinction > 0. {
+                scattering[i] / extinction
+            } else {
+                1.
+            }
+        };
+        let particles = Isotropic::new(Color::new(albedo(0), albedo(1), albedo(2)));
+        Medium {
+            scattering,
+            absorption,
+            particles,
+        }
+    }
+
+    /// A medium where light travels `mean_free_path` on average before
+    /// something happens to it, which is scattering with a probability of
+    /// `albedo` and being absorbed otherwise
+    pub fn from_mean_free_path(albedo: Color, mean_free_path: Color) -> Self {
+        let scattering = |i: usize| albedo[i] / mean_free_path[i];
+        let absorption = |i: usize| (1. - albedo[i]) / mean_free_path[i];
+        Medium::new(
+            Color::new(scattering(0), scattering(1), scattering(2)),
+            Color::new(absorption(0), absorption(1), absorption(2)),
+        )
+    }
+
+    /// Share of the light scattered rather than absorbed, each time
+    /// something happens to it
+    pub fn albedo(&self) -> Color {
+        self.particles.albedo.clone()
+    }
+
+    /// Follows `r_in` through the medium up to `t_max` along it. How far it
+    /// goes is drawn for the hero wavelength, and the other wavelengths are
+    /// weighted by how likely they were to go as far.
+    pub fn flight(&self, r_in: &Ray, t_max: f32) -> Flight {
+        let scattering = r_in.reflectance(&self.scattering);
+        let extinction = scattering.clone() + r_in.reflectance(&self.absorption);
+        let hero = r_in.hero();
+        let length = t_max * r_in.direction.magnitude();
+        let distance = -(1. - rand::thread_rng().gen::<f32>()).ln() / extinction[hero];
+        let transmittance = |i: usize, distance: f32| (-extinction[i] * distance).exp();
+
+        if distance < length {
+            // The phase function is sampled exactly, so it cancels out
+            let mut ray = r_in.spawn(
+                self.particles.phase().generate(),
+                r_in.at(t_max * distance / length),
+            );
+            let value = |i: usize| scattering[i] * transmittance(i, distance);
+            let pdf = [0, 1, 2].map(|i| extinction[i] * transmittance(i, distance));
+            let value = Color::new(value(0), value(1), value(2));
+            let attenuation = r_in.hero_weight(&mut ray, hero, &value, pdf);
+            return Flight::Scattered { attenuation, ray };
+        }
+        let mut ray = r_in.spawn(r_in.direction, r_in.at(t_max));
+        let pdf = [0, 1, 2].map(|i| transmittance(i, length));
+        let value = Color::new(pdf[0], pdf[1], pdf[2]);
+        let attenuation = r_in.hero_weight(&mut ray, hero, &value, pdf);
+        Flight::Through { attenuation, ray }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn free_flights_follow_beer_lambert() {
+        let scattering = Color::new(0.5, 1., 2.);
+        let medium = Medium::new(scattering.clone(), Color::black());
+        // Two units of the medium
+        let r_in = Ray::new(Vec3(0., 0., 1.), Vec3(0., 0., -1.));
+
+        let n = 100000;
+        let mut scattered = Color::black();
+        for _ in 0..n {
+            match medium.flight(&r_in, 2.) {
+                Flight::Scattered { attenuation, ray } => {
+                    assert!(ray.origin.2 < 1.);
+                    assert!(ray.hero.is_some());
+                    scattered += attenuation * (1. / n as f32);
+                }
+                Flight::Through { ray, .. } => {
+                    assert!((ray.origin.2 - 1.).abs() < 1e-5);
+                    assert_eq!(ray.direction, r_in.direction);
+                }
+            }
+        }
+        // Every channel, whichever one the flights were drawn for
+        for i in 0..3 {
+            let expected = 1. - (-2. * scattering[i]).exp();
+            assert!((scattered[i] - expected).abs() < 0.01, "{scattered:?}");
+        }
+    }
+}
@@ -0,0 +1,420 @@
+//! Microfacet models, which treat a rough surface as countless tiny mirrors
+//! whose normals follow the GGX (Trowbridge–Reitz) distribution. Vectors
+//! are in a local frame where the surface normal is the z axis.
+
+use super::Fresnel;
+use crate::{
+    pdf::Pdf,
+    vec3::{onb::Onb, Vec3},
+};
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// GGX distribution of microfacet normals, stretched along the x and y axes
+/// of the local frame by `alpha_x` and `alpha_y`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ggx {
+    pub alpha_x: f32,
+    pub alpha_y: f32,
+}
+
+impl Ggx {
+    /// Same roughness in every direction. The roughness is squared, which
+    /// makes it look more even between `0` and `1`.
+    pub fn new(roughness: f32) -> Self {
+        Ggx::anisotropic(roughness, 0.)
+    }
+
+    /// Roughness stretched along the x axis as `anisotropy` goes from `0`
+    /// to `1`, like brushed metal. Materials line that axis up with a
+    /// tangent they are given.
+    pub fn anisotropic(roughness: f32, anisotropy: f32) -> Self {
+        let alpha = roughness * roughness;
+        let aspect = (1. - 0.9 * anisotropy.clamp(0., 1.)).sqrt();
+        Ggx {
+            alpha_x: (alpha / aspect).max(1e-4),
+            alpha_y: (alpha * aspect).max(1e-4),
+        }
+    }
+
+    /// Whether the distribution is too peaked to sample in floating point,
+    /// and the surface is better treated as a perfect mirror
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    /// Density of microfacets with normal `m`, per unit area of the surface
+    pub fn d(&self, m: &Vec3) -> f32 {
+        if m.2 <= 0. {
+            return 0.;
+        }
+        let e = (m.0 / self.alpha_x).powi(2) + (m.1 / self.alpha_y).powi(2) + m.2 * m.2;
+        1. / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    /// Smith's auxiliary function, from which the shadowing follows
+    fn lambda(&self, w: &Vec3) -> f32 {
+        if w.2 == 0. {
+            return f32::INFINITY;
+        }
+        let tan_squared =
+            ((self.alpha_x * w.0).powi(2) + (self.alpha_y * w.1).powi(2)) / (w.2 * w.2);
+        ((1. + tan_squared).sqrt() - 1.) / 2.
+    }
+
+    /// Fraction of the microfacets seen from `w` that are not hidden behind
+    /// others
+    pub fn g1(&self, w: &Vec3) -> f32 {
+        1. / (1. + self.lambda(w))
+    }
+
+    /// Fraction of the microfacets visible from both directions, accounting
+    /// for the correlation between the two
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        1. / (1. + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the normals drawn by `sample_visible`
+    pub fn visible_pdf(&self, wo: &Vec3, m: &Vec3) -> f32 {
+        self.g1(wo) * wo.dot(m).max(0.) * self.d(m) / wo.2
+    }
+
+    /// Draws a microfacet normal seen from `wo`, which must be above the
+    /// surface, from Heitz, "Sampling the GGX Distribution of Visible
+    /// Normals" (2018)
+    pub fn sample_visible(&self, wo: &Vec3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        // Stretched so the distribution becomes a hemisphere
+        let v = Vec3(self.alpha_x * wo.0, self.alpha_y * wo.1, wo.2).normalize();
+        let length_squared = v.0 * v.0 + v.1 * v.1;
+        let t1 = if length_squared > 0. {
+            Vec3(-v.1, v.0, 0.) / length_squared.sqrt()
+        } else {
+            Vec3(1., 0., 0.)
+        };
+        let t2 = v.cross(&t1);
+
+        // A point on the disk the hemisphere projects to, seen from `v`
+        let r = rng.gen::<f32>().sqrt();
+        let phi = 2. * PI * rng.gen::<f32>();
+        let p1 = r * phi.cos();
+        let s = (1. + v.2) / 2.;
+        let p2 = (1. - s) * (1. - p1 * p1).sqrt() + s * r * phi.sin();
+        let n = p1 * t1 + p2 * t2 + (1. - p1 * p1 - p2 * p2).max(0.).sqrt() * v;
+
+        Vec3(self.alpha_x * n.0, self.alpha_y * n.1, n.2.max(1e-6)).normalize()
+    }
+
+    /// The BSDF of mirror microfacets times the cosine with the normal,
+    /// without the Fresnel term
+    pub fn reflection(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        if wo.2 <= 0. || wi.2 <= 0. {
+            return 0.;
+        }
+        let m = (wo + wi).normalize();
+        self.d(&m) * self.g(wo, wi) / (4. * wo.2)
+    }
+
+    /// The BSDF times the cosine with the normal of a rough boundary
+    /// between two dielectrics, from Walter et al., "Microfacet Models for
+    /// Refraction through Rough Surfaces" (2007). `eta` is the refractive
+    /// index on the far side over the one on the side of `wo`, and `wi` is
+    /// on the far side for transmission.
+    pub fn dielectric(&self, wo: &Vec3, wi: &Vec3, eta: f32) -> f32 {
+        if wo.2 <= 0. {
+            return 0.;
+        }
+        let m = if wi.2 > 0. {
+            reflection_normal(wo, wi)
+        } else {
+            refraction_normal(wo, wi, eta)
+        };
+        let Some(m) = m else {
+            return 0.;
+        };
+        let (cos_o, cos_i) = (wo.dot(&m), wi.dot(&m));
+        let fresnel = Fresnel::Exact.reflectance(cos_o, eta);
+        let d_g = self.d(&m) * self.g(wo, wi);
+        if wi.2 > 0. {
+            fresnel * d_g / (4. * wo.2)
+        } else {
+            // Not divided by eta squared, like the smooth `Dielectric` that
+            // does not scale the radiance passing through it
+            let denominator = cos_o + eta * cos_i;
+            (1. - fresnel) * d_g * eta * eta * (cos_o * cos_i).abs()
+                / (wo.2 * denominator * denominator)
+        }
+    }
+}
+
+/// Microfacet normal reflecting `wo` into `wi`, if there is one facing `wo`
+fn reflection_normal(wo: &Vec3, wi: &Vec3) -> Option<Vec3> {
+    let m = wo + wi;
+    if m.magnitude_squared() < 1e-12 {
+        return None;
+    }
+    let m = m.normalize();
+    (m.2 > 0. && wo.dot(&m) > 0.).then_some(m)
+}
+
+/// Microfacet normal refracting `wo` into `wi`, if there is one facing `wo`
+/// with `wi` on its other side
+fn refraction_normal(wo: &Vec3, wi: &Vec3, eta: f32) -> Option<Vec3> {
+    let m = wo + eta * wi;
+    if m.magnitude_squared() < 1e-12 {
+        return None;
+    }
+    let m = m.normalize();
+    let m = if m.2 < 0. { -m } else { m };
+    (wo.dot(&m) > 0. && wi.dot(&m) < 0.).then_some(m)
+}
+
+/// Directions reflected off visible microfacets around a surface normal
+pub struct ReflectionPdf {
+    frame: Onb,
+    wo: Vec3,
+    distribution: Ggx,
+}
+
+impl ReflectionPdf {
+    /// Reflections of `r_out`, the direction back towards where the light
+    /// goes, around `normal`
+    pub fn new(normal: &Vec3, r_out: &Vec3, distribution: Ggx) -> Self {
+        ReflectionPdf::in_frame(Onb::new(normal), r_out, distribution)
+    }
+
+    /// Same as `new`, with the x axis of the distribution along the `u`
+    /// axis of `frame`, whose `w` axis is the normal
+    pub fn in_frame(frame: Onb, r_out: &Vec3, distribution: Ggx) -> Self {
+        let wo = frame.local(&r_out.normalize());
+        ReflectionPdf {
+            frame,
+            wo,
+            distribution,
+        }
+    }
+}
+
+impl Pdf for ReflectionPdf {
+    fn value(&self, direction: &Vec3) -> f32 {
+        let wi = self.frame.local(&direction.normalize());
+        reflection_normal(&self.wo, &wi).map_or(0., |m| {
+            self.distribution.visible_pdf(&self.wo, &m) / (4. * self.wo.dot(&m))
+        })
+    }
+
+    fn generate(&self) -> Vec3 {
+        let m = self.distribution.sample_visible(&self.wo);
+        self.frame.transform(&(-self.wo).reflect(&m))
+    }
+}
+
+/// Directions reflected or refracted by visible microfacets of a rough
+/// dielectric, each with the probability given by the Fresnel equations
+pub struct DielectricPdf {
+    frame: Onb,
+    wo: Vec3,
+    eta: f32,
+    distribution: Ggx,
+}
+
+impl DielectricPdf {
+    /// Scattering of `r_out` around `normal`, which is on its side, into a
+    /// material whose refractive index is `eta` times the one it comes from
+    pub fn new(normal: &Vec3, r_out: &Vec3, eta: f32, distribution: Ggx) -> Self {
+        let frame = Onb::new(normal);
+        let wo = frame.local(&r_out.normalize());
+        DielectricPdf {
+            frame,
+            wo,
+            eta,
+            distribution,
+        }
+    }
+}
+
+impl Pdf for DielectricPdf {
+    /// Microfacets can reflect below or refract above the surface, where
+    /// the BSDF is zero, so directions on either side may have come from
+    /// both
+    fn value(&self, direction: &Vec3) -> f32 {
+        let wi = self.frame.local(&direction.normalize());
+        let fresnel = |m: &Vec3| Fresnel::Exact.reflectance(self.wo.dot(m), self.eta);
+        let reflected = reflection_normal(&self.wo, &wi).map_or(0., |m| {
+            fresnel(&m) * self.distribution.visible_pdf(&self.wo, &m) / (4. * self.wo.dot(&m))
+        });
+        let refracted = refraction_normal(&self.wo, &wi, self.eta).map_or(0., |m| {
+            let (cos_o, cos_i) = (self.wo.dot(&m), wi.dot(&m));
+            let denominator = cos_o + self.eta * cos_i;
+            (1. - fresnel(&m))
+                * self.distribution.visible_pdf(&self.wo, &m)
+                * self.eta
+                * self.eta
+                * cos_i.abs()
+                / (denominator * denominator)
+        });
+        reflected + refracted
+    }
+
+    fn generate(&self) -> Vec3 {
+        let m = self.distribution.sample_visible(&self.wo);
+        let fresnel = Fresnel::Exact.reflectance(self.wo.dot(&m), self.eta);
+        let wi = if rand::thread_rng().gen::<f32>() < fresnel {
+            (-self.wo).reflect(&m)
+        } else {
+            (-self.wo).refract(&m, 1. / self.eta)
+        };
+        self.frame.transform(&wi)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Estimate of the integral of `f` over the sphere, with one random
+    /// direction in each cell of a grid over the height and the angle
+    /// around the z axis, which cuts the variance on peaked functions
+    fn integrate(f: impl Fn(&Vec3) -> f32) -> f32 {
+        let (heights, angles) = (500, 500);
+        let mut rng = rand::thread_rng();
+        let mut total = 0.;
+        for i in 0..heights {
+            for j in 0..angles {
+                let z = -1. + 2. * (i as f32 + rng.gen::<f32>()) / heights as f32;
+                let phi = 2. * PI * (j as f32 + rng.gen::<f32>()) / angles as f32;
+                let r = (1. - z * z).max(0.).sqrt();
+                total += f(&Vec3(r * phi.cos(), r * phi.sin(), z));
+            }
+        }
+        // Every cell covers the same area
+        total * 4. * PI / (heights * angles) as f32
+    }
+
+    const DISTRIBUTIONS: [(f32, f32); 3] = [(0.5, 0.), (0.8, 0.), (0.6, 0.8)];
+
+    #[test]
+    fn projected_normals_cover_the_surface() {
+        for (roughness, anisotropy) in DISTRIBUTIONS {
+            let ggx = Ggx::anisotropic(roughness, anisotropy);
+            let area = integrate(|m| ggx.d(m) * m.2);
+            assert!((area - 1.).abs() < 0.05, "{roughness} {anisotropy} {area}");
+        }
+    }
+
+    #[test]
+    fn reflection_pdf_integrates_to_one() {
+        for (roughness, anisotropy) in DISTRIBUTIONS {
+            let pdf = ReflectionPdf::in_frame(
+                Onb::new(&Vec3(0., 1., 0.)),
+                &Vec3(0.5, 0.6, 0.),
+                Ggx::anisotropic(roughness, anisotropy),
+            );
+            let total = integrate(|dir| pdf.value(dir));
+            assert!(
+                (total - 1.).abs() < 0.05,
+                "{roughness} {anisotropy} {total}"
+            );
+        }
+    }
+
+    #[test]
+    fn sampling_matches_the_pdf() {
+        // Sampled and uniform estimates of the reflected energy agree only
+        // if the pdf is the density the directions are drawn with
+        for (roughness, anisotropy) in DISTRIBUTIONS {
+            let ggx = Ggx::anisotropic(roughness, anisotropy);
+            let normal = Vec3(0., 0., 1.);
+            let wo = Vec3(0.6, 0.2, 0.5).normalize();
+            let pdf = ReflectionPdf::in_frame(Onb::new(&normal), &wo, ggx);
+
+            let n = 100000;
+            let sampled = (0..n)
+                .map(|_| {
+                    let wi = pdf.generate().normalize();
+                    ggx.reflection(&wo, &wi) / pdf.value(&wi)
+                })
+                .sum::<f32>()
+                / n as f32;
+            let uniform = integrate(|wi| ggx.reflection(&wo, wi));
+            assert!(sampled <= 1. && uniform <= 1.);
+            assert!((sampled - uniform).abs() < 0.03, "{sampled} {uniform}");
+        }
+    }
+
+    #[test]
+    fn dielectric_pdf_integrates_to_one() {
+        for eta in [1.5, 1. / 1.5] {
+            for roughness in [0.5, 0.8] {
+                let pdf = DielectricPdf::new(
+                    &Vec3(0., 0., 1.),
+                    &Vec3(0.4, 0., 1.),
+                    eta,
+                    Ggx::new(roughness),
+                );
+                let total = integrate(|dir| pdf.value(dir));
+                assert!((total - 1.).abs() < 0.05, "{eta} {roughness} {total}");
+            }
+        }
+    }
+
+    #[test]
+    fn dielectric_sampling_matches_the_pdf() {
+        for eta in [1.5, 1. / 1.5] {
+            let ggx = Ggx::new(0.7);
+            let wo = Vec3(0.5, 0., 0.7).normalize();
+            let pdf = DielectricPdf::new(&Vec3(0., 0., 1.), &wo, eta, ggx);
+            // The frame of the pdf can point x and y elsewhere
+            let local = |dir: &Vec3| pdf.frame.local(dir);
+            let wo_local = local(&wo);
+
+            let n = 100000;
+            let (mut reflected, mut transmitted) = (0., 0.);
+            for _ in 0..n {
+                let wi = pdf.generate().normalize();
+                let weight = ggx.dielectric(&wo_local, &local(&wi), eta) / pdf.value(&wi);
+                assert!(weight.is_finite());
+                // Never more than one, as only shadowing loses light
+                assert!(weight <= 1. + 1e-3, "{eta} {weight}");
+                if local(&wi).2 > 0. {
+                    reflected += weight / n as f32;
+                } else {
+                    transmitted += weight / n as f32;
+                }
+            }
+            // Every direction folded onto one side, so no samples are wasted
+            let uniform = |side: f32| {
+                integrate(|wi| {
+                    let wi = local(wi);
+                    let wi = Vec3(wi.0, wi.1, wi.2.abs() * side);
+                    ggx.dielectric(&wo_local, &wi, eta) / 2.
+                })
+            };
+            let (uniform_reflected, uniform_transmitted) = (uniform(1.), uniform(-1.));
+            assert!(
+                (reflected - uniform_reflected).abs() < 0.02,
+                "{eta} {reflected} {uniform_reflected}"
+            );
+            assert!(
+                (transmitted - uniform_transmitted).abs() < 0.04,
+                "{eta} {transmitted} {uniform_transmitted}"
+            );
+            // Going out, much of the light is scattered back below the
+            // surface by the rough facets and lost
+            if eta > 1. {
+                assert!(reflected + transmitted > 0.85, "{reflected} {transmitted}");
+            }
+        }
+    }
+
+    #[test]
+    fn visible_normals_face_the_viewer() {
+        let ggx = Ggx::anisotropic(0.9, 0.5);
+        let wo = Vec3(0.9, 0., 0.1).normalize();
+        for _ in 0..1000 {
+            let m = ggx.sample_visible(&wo);
+            assert!(m.2 > 0. && (m.magnitude() - 1.).abs() < 1e-4);
+            assert!(wo.dot(&m) >= -1e-4);
+        }
+    }
+}
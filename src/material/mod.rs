@@ -0,0 +1,814 @@
+use rand::Rng;
+
+use crate::{
+    color::Color,
+    hittable,
+    pdf::{CosinePdf, Pdf, SpherePdf},
+    ray::Ray,
+    spectrum::RGB_WAVELENGTHS,
+    texture::Texture,
+    vec3::{Point3, Vec3},
+};
+use std::f32::consts::PI;
+
+pub mod coated;
+pub mod conductor;
+pub mod medium;
+pub mod microfacet;
+pub mod oren_nayar;
+pub mod principled;
+pub mod rough_dielectric;
+pub mod subsurface;
+pub mod thin_film;
+
+/// How a material scatters an incoming ray
+pub enum ScatterRecord {
+    /// All the light leaves along a single ray, like a perfect mirror. These
+    /// cannot be sampled from the lights.
+    Specular { attenuation: Color, ray: Ray },
+    /// A step of a random walk through a medium, followed like `Specular`.
+    /// Walks can take many steps, so the path tracer and photon mapper do
+    /// not count these against their bounce limit.
+    Walk { attenuation: Color, ray: Ray },
+    /// Light is spread over many directions. Directions are drawn from the
+    /// pdf and weighted by `Material::eval`.
+    Pdf(Box<dyn Pdf>),
+}
+
+pub trait Material: Sync {
+    fn scatter(&self, r_in: &Ray, hit_rec: &hittable::HitRecord) -> Option<ScatterRecord>;
+
+    /// The BSDF times the cosine of `scattered` with the normal, for
+    /// materials that return `ScatterRecord::Pdf`
+    fn eval(&self, _r_in: &Ray, _hit_rec: &hittable::HitRecord, _scattered: &Ray) -> Color {
+        Color::black()
+    }
+
+    /// Light given off by the surface itself
+    fn emitted(&self, _r_in: &Ray, _hit_rec: &hittable::HitRecord) -> Color {
+        Color::black()
+    }
+
+    /// Base color of the surface, for inspecting the scene
+    fn albedo(&self, _hit_rec: &hittable::HitRecord) -> Color {
+        Color::black()
+    }
+}
+
+pub struct Lambertian {
+    albedo: Box<dyn Texture<Color>>,
+}
+
+impl Lambertian {
+    pub fn new(albedo: Color) -> Self {
+        Lambertian::textured(albedo)
+    }
+
+    pub fn textured(albedo: impl Texture<Color> + 'static) -> Self {
+        Lambertian {
+            albedo: Box::new(albedo),
+        }
+    }
+}
+
+impl Material for Lambertian {
+    fn scatter(&self, _: &Ray, hit_rec: &hittable::HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Pdf(Box::new(CosinePdf::new(
+            &hit_rec.normal,
+        ))))
+    }
+
+    fn eval(&self, r_in: &Ray, hit_rec: &hittable::HitRecord, scattered: &Ray) -> Color {
+        let cosine = hit_rec.normal.dot(&scattered.direction.normalize());
+        r_in.reflectance(&self.albedo.value(&hit_rec.p)) * (cosine / PI).max(0.)
+    }
+
+    fn albedo(&self, hit_rec: &hittable::HitRecord) -> Color {
+        self.albedo.value(&hit_rec.p)
+    }
+}
+
+pub struct Metal {
+    albedo: Color,
+    fuzz: f32,
+}
+
+impl Metal {
+    pub fn new(albedo: Color, fuzz: f32) -> Self {
+        Metal {
+            albedo,
+            fuzz: if fuzz < 1. { fuzz } else { 1. },
+        }
+    }
+}
+
+impl Material for Metal {
+    fn scatter(&self, r_in: &Ray, hit_rec: &hittable::HitRecord) -> Option<ScatterRecord> {
+        let reflection_dir =
+            r_in.direction.reflect(&hit_rec.normal) + (self.fuzz * Vec3::random_unit_vector());
+        // Catching degenerate scatter direction
+        if reflection_dir.dot(&(hit_rec.normal)) > 0. {
+            Some(ScatterRecord::Specular {
+                attenuation: r_in.reflectance(&self.albedo),
+                ray: r_in.spawn(reflection_dir, hit_rec.p),
+            })
+        } else {
+            None
+        }
+    }
+
+    fn albedo(&self, _: &hittable::HitRecord) -> Color {
+        self.albedo.clone()
+    }
+}
+
+/// Glass, water and other clear materials, which reflect or refract light
+/// depending on the angle it arrives at
+pub struct Dielectric {
+    refractive_index: f32,
+    /// Fraction of light absorbed per unit of distance traveled inside
+    absorption: Color,
+    /// Thickness of a sheet modelled by a single surface, when the glass is
+    /// thin walled
+    thickness: Option<f32>,
+    fresnel: Fresnel,
+    film: Option<thin_film::ThinFilm>,
+}
+
+impl Dielectric {
+    pub fn new(refractive_index: f32) -> Self {
+        Dielectric {
+            refractive_index,
+            absorption: Color::black(),
+            thickness: None,
+            fresnel: Fresnel::default(),
+            film: None,
+        }
+    }
+
+    pub fn with_fresnel(self, fresnel: Fresnel) -> Self {
+        Dielectric { fresnel, ..self }
+    }
+
+    /// Covers the surface with a film, which reflects light in place of
+    /// the Fresnel equations. Glass with a refractive index of one is a
+    /// film on its own, like a soap bubble.
+    pub fn with_thin_film(self, film: thin_film::ThinFilm) -> Self {
+        Dielectric {
+            film: Some(film),
+            ..self
+        }
+    }
+
+    /// Glass that absorbs light following the Beer–Lambert law, so light
+    /// that went `distance` through it is left with `color`. Surfaces must
+    /// enclose the glass, since the distance is measured between entering
+    /// and leaving it.
+    pub fn tinted(refractive_index: f32, color: Color, distance: f32) -> Self {
+        Dielectric {
+            absorption: absorption_coefficient(&color, distance),
+            ..Dielectric::new(refractive_index)
+        }
+    }
+
+    /// Makes the surface a sheet of glass of the given thickness, like a
+    /// window pane. Light passes straight through without being bent and
+    /// reflects off both sides of the sheet.
+    pub fn thin_walled(self, thickness: f32) -> Self {
+        Dielectric {
+            thickness: Some(thickness),
+            ..self
+        }
+    }
+
+    /// Fraction of the light reflected and transmitted by a thin sheet,
+    /// summing every path bouncing back and forth between its two sides,
+    /// which each reflect `reflect`
+    fn thin_wall(
+        &self,
+        cosine: f32,
+        thickness: f32,
+        reflect: &Color,
+        absorption: &Color,
+    ) -> (Color, Color) {
+        let sin_refracted = (1. - cosine * cosine).sqrt() / self.refractive_index;
+        let distance = thickness / (1. - sin_refracted * sin_refracted).sqrt();
+
+        let mut reflected = Color::black();
+        let mut transmitted = Color::black();
+        for i in 0..3 {
+            let (reflect, transmit) = (reflect[i], 1. - reflect[i]);
+            let kept = (-absorption[i] * distance).exp();
+            let bounces = 1. - (reflect * kept).powi(2);
+            reflected[i] = reflect + transmit * transmit * reflect * kept * kept / bounces;
+            transmitted[i] = transmit * transmit * kept / bounces;
+        }
+        (reflected, transmitted)
+    }
+
+    /// Fraction of light reflected by the surface, arriving at `cosine` to
+    /// the normal from outside or, leaving the glass, from inside
+    fn reflectance(&self, r_in: &Ray, p: &Point3, cosine: f32, front_face: bool) -> Color {
+        let (outside, inside) = if front_face {
+            (1., self.refractive_index)
+        } else {
+            (self.refractive_index, 1.)
+        };
+        match &self.film {
+            Some(film) => film.reflectance(r_in, p, cosine, outside, |_| (inside, 0.)),
+            None => {
+                let reflect = self.fresnel.reflectance(cosine, inside / outside);
+                Color::new(reflect, reflect, reflect)
+            }
+        }
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, r_in: &Ray, hit_rec: &hittable::HitRecord) -> Option<ScatterRecord> {
+        let absorption = r_in.reflectance(&self.absorption);
+        let unit_r_in_dir = r_in.direction.normalize();
+        let cosine = (-unit_r_in_dir).dot(&hit_rec.normal).min(1.);
+        let (reflected, transmitted, refracted) = match self.thickness {
+            Some(thickness) => {
+                let reflect = self.reflectance(r_in, &hit_rec.p, cosine, true);
+                let (reflected, transmitted) =
+                    self.thin_wall(cosine, thickness, &reflect, &absorption);
+                (reflected, transmitted, unit_r_in_dir)
+            }
+            None => {
+                // Leaving the glass, after traveling from where the ray entered
+                let attenuation = if hit_rec.front_face {
+                    Color::new(1., 1., 1.)
+                } else {
+                    let distance = hit_rec.t * r_in.direction.magnitude();
+                    let channel = |i: usize| (-absorption[i] * distance).exp();
+                    Color::new(channel(0), channel(1), channel(2))
+                };
+                if self.film.is_none() {
+                    return Some(ScatterRecord::Specular {
+                        attenuation,
+                        ray: r_in.spawn(
+                            refract_or_reflect(r_in, hit_rec, self.refractive_index, self.fresnel),
+                            hit_rec.p,
+                        ),
+                    });
+                }
+                let eta = if hit_rec.front_face {
+                    self.refractive_index
+                } else {
+                    1. / self.refractive_index
+                };
+                let reflected = if (1. - cosine * cosine) / (eta * eta) >= 1. {
+                    // Total internal reflection
+                    Color::new(1., 1., 1.)
+                } else {
+                    self.reflectance(r_in, &hit_rec.p, cosine, hit_rec.front_face)
+                };
+                let channel = |i: usize| 1. - reflected[i];
+                let transmitted = Color::new(channel(0), channel(1), channel(2));
+                (
+                    reflected * attenuation.clone(),
+                    transmitted * attenuation,
+                    unit_r_in_dir.refract(&hit_rec.normal, 1. / eta),
+                )
+            }
+        };
+
+        // Colored reflections are split by the share of light each way
+        let total = |c: &Color| c[0] + c[1] + c[2];
+        if total(&reflected) + total(&transmitted) == 0. {
+            // All of it was absorbed on the way
+            return None;
+        }
+        let reflect_probability = total(&reflected) / (total(&reflected) + total(&transmitted));
+        let (attenuation, direction) = if rand::thread_rng().gen::<f32>() < reflect_probability {
+            (
+                reflected * (1. / reflect_probability),
+                unit_r_in_dir.reflect(&hit_rec.normal),
+            )
+        } else {
+            (transmitted * (1. / (1. - reflect_probability)), refracted)
+        };
+        Some(ScatterRecord::Specular {
+            attenuation,
+            ray: r_in.spawn(direction, hit_rec.p),
+        })
+    }
+
+    /// Color left after light traveled a unit of distance through the glass
+    fn albedo(&self, _: &hittable::HitRecord) -> Color {
+        let channel = |i: usize| (-self.absorption[i]).exp();
+        Color::new(channel(0), channel(1), channel(2))
+    }
+}
+
+/// How the refractive index of a glass changes with the wavelength, given
+/// in micrometers in the formulas
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dispersion {
+    /// `n = a + b / λ²`, which is good enough close to the visible range
+    Cauchy { a: f32, b: f32 },
+    /// `n² = 1 + Σ b λ² / (λ² - c)`, the form glass catalogs are given in
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Dispersion {
+    /// Schott N-BK7, the crown glass of most lenses
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.039612, 0.23179234, 1.0104695],
+        c: [0.0060006987, 0.020017914, 103.56065],
+    };
+    /// Schott N-SF11, a dense flint glass that spreads colors far apart
+    pub const FLINT: Dispersion = Dispersion::Sellmeier {
+        b: [1.737597, 0.31374735, 1.898781],
+        c: [0.013188707, 0.062306814, 155.2363],
+    };
+    pub const DIAMOND: Dispersion = Dispersion::Sellmeier {
+        b: [0.3306, 4.3356, 0.],
+        c: [0.030625, 0.011236, 0.],
+    };
+    pub const WATER: Dispersion = Dispersion::Cauchy {
+        a: 1.324,
+        b: 0.00306,
+    };
+
+    /// Refractive index at a wavelength in nanometers
+    pub fn refractive_index(&self, lambda: f32) -> f32 {
+        let micrometers = lambda / 1000.;
+        let squared = micrometers * micrometers;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / squared,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f32 = (0..3).map(|i| b[i] * squared / (squared - c[i])).sum();
+                (1. + sum).sqrt()
+            }
+        }
+    }
+}
+
+/// Clear glass whose refractive index depends on the wavelength, so light
+/// of different colors refracts in different directions. Paths going
+/// through it follow the hero wavelength, or in RGB a single color channel,
+/// and the others only carry light that could have taken the same path.
+#[derive(Clone)]
+pub struct Dispersive {
+    dispersion: Dispersion,
+}
+
+impl Dispersive {
+    pub fn new(dispersion: Dispersion) -> Self {
+        Dispersive { dispersion }
+    }
+}
+
+impl Material for Dispersive {
+    fn scatter(&self, r_in: &Ray, hit_rec: &hittable::HitRecord) -> Option<ScatterRecord> {
+        let hero = r_in.hero();
+        let lambda = match &r_in.wavelengths {
+            Some(wavelengths) => wavelengths.0[hero],
+            None => RGB_WAVELENGTHS[hero],
+        };
+        let refractive_index = self.dispersion.refractive_index(lambda);
+
+        let mut ray = r_in.spawn(
+            refract_or_reflect(r_in, hit_rec, refractive_index, Fresnel::default()),
+            hit_rec.p,
+        );
+        // Other wavelengths refract elsewhere, so only the hero could have
+        // gone this way
+        let mut value = Color::black();
+        value[hero] = 1.;
+        let mut pdf = [0.; 3];
+        pdf[hero] = 1.;
+        let attenuation = r_in.hero_weight(&mut ray, hero, &value, pdf);
+        Some(ScatterRecord::Specular { attenuation, ray })
+    }
+
+    fn albedo(&self, _: &hittable::HitRecord) -> Color {
+        Color::new(1., 1., 1.)
+    }
+}
+
+/// Emissive surface that does not reflect any light. Only the front face
+/// glows.
+pub struct DiffuseLight {
+    emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        DiffuseLight { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _: &Ray, _: &hittable::HitRecord) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, r_in: &Ray, hit_rec: &hittable::HitRecord) -> Color {
+        if hit_rec.front_face {
+            r_in.illuminant(&self.emit)
+        } else {
+            Color::black()
+        }
+    }
+}
+
+/// Scatters uniformly in every direction, like the particles of a fog
+pub struct Isotropic {
+    albedo: Color,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Isotropic { albedo }
+    }
+
+    /// The phase function, which is how likely light is to be scattered in
+    /// each direction
+    pub fn phase(&self) -> SpherePdf {
+        SpherePdf
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, _: &Ray, _: &hittable::HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Pdf(Box::new(self.phase())))
+    }
+
+    fn eval(&self, r_in: &Ray, _: &hittable::HitRecord, scattered: &Ray) -> Color {
+        r_in.reflectance(&self.albedo) * self.phase().value(&scattered.direction)
+    }
+
+    fn albedo(&self, _: &hittable::HitRecord) -> Color {
+        self.albedo.clone()
+    }
+}
+
+/// Fraction of light absorbed per unit of distance by a medium that leaves
+/// light which went `distance` through it with `color`
+fn absorption_coefficient(color: &Color, distance: f32) -> Color {
+    let channel = |i: usize| -color[i].max(1e-6).ln() / distance;
+    Color::new(channel(0), channel(1), channel(2))
+}
+
+/// How the share of light a dielectric reflects is computed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Fresnel {
+    /// Schlick's approximation, which is cheap and close except towards
+    /// grazing angles
+    #[default]
+    Schlick,
+    /// The Fresnel equations for unpolarized light
+    Exact,
+}
+
+impl Fresnel {
+    /// Fraction of the light reflected when arriving at `cos_incident` to
+    /// the normal, into a medium whose refractive index is `eta` times the
+    /// one the light comes from
+    pub fn reflectance(&self, cos_incident: f32, eta: f32) -> f32 {
+        let sin_transmitted_squared = (1. - cos_incident * cos_incident) / (eta * eta);
+        // Total internal reflection
+        if sin_transmitted_squared >= 1. {
+            return 1.;
+        }
+        let cos_transmitted = (1. - sin_transmitted_squared).sqrt();
+        match self {
+            Fresnel::Schlick => {
+                let r0 = ((eta - 1.) / (eta + 1.)).powi(2);
+                // The formula takes the angle on the side of the lower index
+                let cosine = if eta < 1. {
+                    cos_transmitted
+                } else {
+                    cos_incident
+                };
+                r0 + (1. - r0) * (1. - cosine).powi(5)
+            }
+            Fresnel::Exact => {
+                let parallel =
+                    (eta * cos_incident - cos_transmitted) / (eta * cos_incident + cos_transmitted);
+                let perpendicular =
+                    (cos_incident - eta * cos_transmitted) / (cos_incident + eta * cos_transmitted);
+                (parallel * parallel + perpendicular * perpendicular) / 2.
+            }
+        }
+    }
+}
+
+/// Picks one of several distributions with a probability proportional to
+/// its weight
+struct Lobes {
+    lobes: Vec<(f32, Box<dyn Pdf>)>,
+}
+
+impl Lobes {
+    fn new(lobes: Vec<(f32, Box<dyn Pdf>)>) -> Self {
+        let total: f32 = lobes.iter().map(|(weight, _)| weight).sum();
+        Lobes {
+            lobes: lobes
+                .into_iter()
+                .filter(|(weight, _)| *weight > 0.)
+                .map(|(weight, pdf)| (weight / total, pdf))
+                .collect(),
+        }
+    }
+}
+
+impl Pdf for Lobes {
+    fn value(&self, direction: &Vec3) -> f32 {
+        self.lobes
+            .iter()
+            .map(|(weight, pdf)| weight * pdf.value(direction))
+            .sum()
+    }
+
+    fn generate(&self) -> Vec3 {
+        let mut pick = rand::thread_rng().gen::<f32>();
+        for (weight, pdf) in &self.lobes {
+            if pick < *weight {
+                return pdf.generate();
+            }
+            pick -= weight;
+        }
+        // Rounding left the pick past the last weight
+        self.lobes[self.lobes.len() - 1].1.generate()
+    }
+}
+
+/// Direction a ray continues in after hitting a surface between air and a
+/// material of the given refractive index, reflected with the probability
+/// given by `fresnel` and refracted otherwise
+fn refract_or_reflect(
+    r_in: &Ray,
+    hit_rec: &hittable::HitRecord,
+    refractive_index: f32,
+    fresnel: Fresnel,
+) -> Vec3 {
+    let unit_r_in_dir = r_in.direction.normalize();
+    let eta = if hit_rec.front_face {
+        // Air into the material
+        refractive_index
+    } else {
+        // Material into the air
+        1. / refractive_index
+    };
+    let cos_theta = ((-unit_r_in_dir).dot(&hit_rec.normal)).min(1.);
+
+    if rand::thread_rng().gen::<f32>() < fresnel.reflectance(cos_theta, eta) {
+        unit_r_in_dir.reflect(&hit_rec.normal)
+    } else {
+        unit_r_in_dir.refract(&hit_rec.normal, 1. / eta)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{material::thin_film::ThinFilm, spectrum::Wavelengths};
+
+    fn assert_close(a: &Color, b: &Color) {
+        for channel in 0..3 {
+            assert!((a[channel] - b[channel]).abs() < 1e-4, "{a:?} {b:?}");
+        }
+    }
+
+    #[test]
+    fn tint_depends_on_distance_inside() {
+        let color = Color::new(0.2, 0.5, 0.9);
+        let glass = Dielectric::tinted(1.5, color.clone(), 2.);
+        let normal = Vec3(0., 0., 1.);
+        let attenuation = |r_in: &Ray, front_face: bool| {
+            let hit_rec = hittable::HitRecord {
+                p: r_in.at(1.),
+                normal: if front_face { normal } else { -normal },
+                t: 1.,
+                front_face,
+                material: &glass,
+                object_id: 0,
+            };
+            match glass.scatter(r_in, &hit_rec) {
+                Some(ScatterRecord::Specular { attenuation, .. }) => attenuation,
+                _ => panic!("glass scatters specularly"),
+            }
+        };
+
+        // Entering is free, leaving pays for the distance traveled inside
+        let r_in = Ray::new(Vec3(0., 0., -2.), Vec3(0., 0., 2.));
+        assert_close(&attenuation(&r_in, true), &Color::new(1., 1., 1.));
+        assert_close(&attenuation(&r_in, false), &color);
+        let r_in = Ray::new(Vec3(0., 0., -4.), Vec3(0., 0., 2.));
+        assert_close(&attenuation(&r_in, false), &(color.clone() * color));
+    }
+
+    #[test]
+    fn dark_filmed_glass_absorbs_everything() {
+        let glass = Dielectric::tinted(1.5, Color::black(), 1.).with_thin_film(ThinFilm {
+            refractive_index: 1.33,
+            thickness: Box::new(400.),
+        });
+        // Leaving after a long way through the glass
+        let hit_rec = hittable::HitRecord {
+            p: Vec3(0., 0., 100.),
+            normal: Vec3(0., 0., -1.),
+            t: 100.,
+            front_face: false,
+            material: &glass,
+            object_id: 0,
+        };
+        let r_in = Ray::new(Vec3(0., 0., 1.), Vec3(0., 0., 0.));
+        assert!(glass.scatter(&r_in, &hit_rec).is_none());
+    }
+
+    #[test]
+    fn thin_walls_conserve_energy() {
+        let glass = Dielectric::new(1.5).thin_walled(0.01);
+        for cosine in [1., 0.7, 0.2] {
+            let reflect = Fresnel::Schlick.reflectance(cosine, 1.5);
+            let surface = Color::new(reflect, reflect, reflect);
+            let (reflected, transmitted) = glass.thin_wall(cosine, 0.01, &surface, &Color::black());
+            assert_close(
+                &reflected,
+                &(Color::new(1., 1., 1.) * (2. * reflect / (1. + reflect))),
+            );
+            assert_close(&(reflected + transmitted), &Color::new(1., 1., 1.));
+
+            // Absorbed light is lost
+            let (reflected, transmitted) =
+                glass.thin_wall(cosine, 0.01, &surface, &Color::new(10., 0., 50.));
+            let sum = reflected + transmitted;
+            assert!(sum[0] < 1. && sum[2] < sum[0] && (sum[1] - 1.).abs() < 1e-4);
+        }
+
+        // Light goes through without changing direction
+        let hit_rec = hittable::HitRecord::from_outside(Vec3(0., 0., 0.), Vec3(0., 0., 1.), &glass);
+        let direction = Vec3(0.3, 0., -1.).normalize();
+        let r_in = Ray::new(direction, Vec3(-0.3, 0., 1.));
+        let refracted = (0..100).any(|_| match glass.scatter(&r_in, &hit_rec) {
+            Some(ScatterRecord::Specular { ray, .. }) => {
+                (ray.direction - direction).magnitude() < 1e-5
+            }
+            _ => false,
+        });
+        assert!(refracted);
+    }
+
+    /// Fraction of rays reflected by glass at `angle` degrees from the
+    /// normal, from outside or inside
+    fn reflected_fraction(glass: &Dielectric, angle: f32, front_face: bool) -> f32 {
+        let normal = if front_face {
+            Vec3(0., 0., 1.)
+        } else {
+            Vec3(0., 0., -1.)
+        };
+        let hit_rec = hittable::HitRecord {
+            p: Vec3(0., 0., 0.),
+            normal,
+            t: 1.,
+            front_face,
+            material: glass,
+            object_id: 0,
+        };
+        let direction = Vec3(angle.to_radians().sin(), 0., 0.) - angle.to_radians().cos() * normal;
+        let r_in = Ray::new(direction, -direction);
+        let samples = 20000;
+        let reflected = (0..samples)
+            .filter(|_| match glass.scatter(&r_in, &hit_rec) {
+                Some(ScatterRecord::Specular { ray, .. }) => ray.direction.dot(&normal) > 0.,
+                _ => panic!("glass scatters specularly"),
+            })
+            .count();
+        reflected as f32 / samples as f32
+    }
+
+    /// Fresnel reflectance from the angles on both sides of the surface
+    fn fresnel_from_angles(incident: f32, n_incident: f32, n_transmitted: f32) -> f32 {
+        let incident = incident.to_radians();
+        let sin_transmitted = incident.sin() * n_incident / n_transmitted;
+        if sin_transmitted >= 1. {
+            return 1.;
+        }
+        if incident == 0. {
+            return ((n_incident - n_transmitted) / (n_incident + n_transmitted)).powi(2);
+        }
+        let transmitted = sin_transmitted.asin();
+        let s = ((incident - transmitted).sin() / (incident + transmitted).sin()).powi(2);
+        let p = ((incident - transmitted).tan() / (incident + transmitted).tan()).powi(2);
+        (s + p) / 2.
+    }
+
+    fn assert_reflects(glass: &Dielectric, angle: f32, front_face: bool, expected: f32) {
+        let fraction = reflected_fraction(glass, angle, front_face);
+        // Five standard deviations of the binomial count
+        let tolerance = 5. * (expected * (1. - expected) / 20000.).sqrt() + 1e-3;
+        assert!(
+            (fraction - expected).abs() < tolerance,
+            "{angle} {front_face} {fraction} {expected}"
+        );
+    }
+
+    #[test]
+    fn exact_fresnel_reflection_ratios() {
+        let glass = Dielectric::new(1.5).with_fresnel(Fresnel::Exact);
+        for angle in [0., 30., 60., 80., 89.] {
+            assert_reflects(&glass, angle, true, fresnel_from_angles(angle, 1., 1.5));
+        }
+        // Past the critical angle of about 41.8° everything is reflected
+        for angle in [0., 20., 40., 42., 70.] {
+            assert_reflects(&glass, angle, false, fresnel_from_angles(angle, 1.5, 1.));
+        }
+        assert!((fresnel_from_angles(0., 1., 1.5) - 0.04).abs() < 1e-6);
+    }
+
+    #[test]
+    fn schlick_reflection_ratios() {
+        let glass = Dielectric::new(1.5);
+        let schlick = |cosine: f32| 0.04 + 0.96 * (1. - cosine).powi(5);
+        for angle in [0., 30., 60., 80., 89.] {
+            let cosine = f32::to_radians(angle).cos();
+            assert_reflects(&glass, angle, true, schlick(cosine));
+            // Close to the real thing
+            assert!((schlick(cosine) - fresnel_from_angles(angle, 1., 1.5)).abs() < 0.03);
+        }
+        for angle in [0., 20., 40., 42., 70.] {
+            let sin_transmitted = f32::to_radians(angle).sin() * 1.5;
+            let expected = if sin_transmitted < 1. {
+                schlick((1. - sin_transmitted * sin_transmitted).sqrt())
+            } else {
+                1.
+            };
+            assert_reflects(&glass, angle, false, expected);
+        }
+    }
+
+    #[test]
+    fn refraction_follows_snell() {
+        let glass = Dielectric::new(1.5);
+        let hit_rec = hittable::HitRecord::from_outside(Vec3(0., 0., 0.), Vec3(0., 0., 1.), &glass);
+        let incident = f32::to_radians(50.);
+        let r_in = Ray::new(Vec3(incident.sin(), 0., -incident.cos()), Vec3(0., 0., 1.));
+        let refracted = (0..100)
+            .find_map(|_| match glass.scatter(&r_in, &hit_rec) {
+                Some(ScatterRecord::Specular { ray, .. }) if ray.direction[2] < 0. => Some(ray),
+                _ => None,
+            })
+            .unwrap();
+        let sin_transmitted = refracted.direction.normalize()[0];
+        assert!((sin_transmitted * 1.5 - incident.sin()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn catalog_refractive_indices() {
+        // At the helium d line
+        for (dispersion, expected) in [
+            (Dispersion::BK7, 1.5168),
+            (Dispersion::FLINT, 1.7847),
+            (Dispersion::DIAMOND, 2.4175),
+            (Dispersion::WATER, 1.333),
+        ] {
+            let n = dispersion.refractive_index(587.56);
+            assert!((n - expected).abs() < 1e-3, "{dispersion:?} {n}");
+            // Blue light bends more than red
+            assert!(dispersion.refractive_index(450.) > dispersion.refractive_index(650.));
+        }
+
+        let cauchy = Dispersion::Cauchy { a: 1.5, b: 0.004 };
+        assert!((cauchy.refractive_index(500.) - 1.516).abs() < 1e-5);
+    }
+
+    #[test]
+    fn dispersive_paths_keep_one_channel() {
+        let material = Dispersive::new(Dispersion::FLINT);
+        let hit_rec =
+            hittable::HitRecord::from_outside(Vec3(0., 0., 0.), Vec3(0., 1., 0.), &material);
+        let rgb = Ray::new(Vec3(1., -1., 0.), Vec3(-1., 1., 0.));
+        let mut spectral = Ray::new(Vec3(1., -1., 0.), Vec3(-1., 1., 0.));
+        spectral.wavelengths = Some(Wavelengths::sample());
+
+        for r_in in [&rgb, &spectral] {
+            let Some(ScatterRecord::Specular { attenuation, ray }) =
+                material.scatter(r_in, &hit_rec)
+            else {
+                panic!("glass scatters specularly");
+            };
+            let hero = ray.hero.unwrap();
+            assert_eq!(attenuation.max_component(), 3.);
+            assert_eq!(attenuation[hero], 3.);
+            if r_in.wavelengths.is_some() {
+                assert_eq!(hero, 0);
+            }
+
+            // Later hits stay on the hero without weighting it again
+            let Some(ScatterRecord::Specular { attenuation, ray }) =
+                material.scatter(&ray, &hit_rec)
+            else {
+                panic!("glass scatters specularly");
+            };
+            assert_eq!(ray.hero, Some(hero));
+            assert_eq!(attenuation[hero], 1.);
+            assert_eq!(attenuation.max_component(), 1.);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Rough diffuse surfaces like clay and concrete, after Oren and Nayar,
+//! "Generalization of Lambert's Reflectance Model" (1994)
+
+use super::{Material, ScatterRecord};
+use crate::{color::Color, hittable, pdf::CosinePdf, ray::Ray, vec3::onb::Onb};
+use std::f32::consts::PI;
+
+/// Diffuse surface made of tiny lambertian facets. Unlike `Lambertian` it
+/// looks flatter, and reflects more light back towards where it came from.
+pub struct OrenNayar {
+    albedo: Color,
+    a: f32,
+    b: f32,
+}
+
+impl OrenNayar {
+    /// `sigma` is the standard deviation of the angle of the facets to the
+    /// surface, in radians. At zero the surface is lambertian.
+    pub fn new(albedo: Color, sigma: f32) -> Self {
+        let sigma_squared = sigma * sigma;
+        OrenNayar {
+            albedo,
+            a: 1. - sigma_squared / (2. * (sigma_squared + 0.33)),
+            b: 0.45 * sigma_squared / (sigma_squared + 0.09),
+        }
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(&self, _: &Ray, hit_rec: &hittable::HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Pdf(Box::new(CosinePdf::new(
+            &hit_rec.normal,
+        ))))
+    }
+
+    fn eval(&self, r_in: &Ray, hit_rec: &hittable::HitRecord, scattered: &Ray) -> Color {
+        let frame = Onb::new(&hit_rec.normal);
+        let wo = frame.local(&-r_in.direction.normalize());
+        let wi = frame.local(&scattered.direction.normalize());
+        if wo.2 <= 0. || wi.2 <= 0. {
+            return Color::black();
+        }
+        let sin_o = (1. - wo.2 * wo.2).max(0.).sqrt();
+        let sin_i = (1. - wi.2 * wi.2).max(0.).sqrt();
+        // Cosine of the angle between the directions around the normal
+        let cos_phi = if sin_o > 1e-4 && sin_i > 1e-4 {
+            ((wo.0 * wi.0 + wo.1 * wi.1) / (sin_o * sin_i)).max(0.)
+        } else {
+            0.
+        };
+        // Sine of the larger angle to the normal and tangent of the smaller
+        let (sin_alpha, tan_beta) = if wi.2 > wo.2 {
+            (sin_o, sin_i / wi.2)
+        } else {
+            (sin_i, sin_o / wo.2)
+        };
+        let scale = self.a + self.b * cos_phi * sin_alpha * tan_beta;
+        r_in.reflectance(&self.albedo) * (scale * wi.2 / PI)
+    }
+
+    fn albedo(&self, _: &hittable::HitRecord) -> Color {
+        self.albedo.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{material::Lambertian, vec3::Vec3};
+
+    #[test]
+    fn smooth_facets_are_lambertian() {
+        let albedo = Color::new(0.8, 0.5, 0.2);
+        let smooth = OrenNayar::new(albedo.clone(), 0.);
+        let lambertian = Lambertian::new(albedo.clone());
+        let rough = OrenNayar::new(albedo, 0.5);
+        let hit_rec =
+            hittable::HitRecord::from_outside(Vec3(0., 0., 0.), Vec3(0., 0., 1.), &smooth);
+        let directions = [
+            Vec3(0., 0., 1.),
+            Vec3(0.5, 0.2, 1.),
+            Vec3(-2., 0.3, 0.4),
+            Vec3(0.1, -3., 0.2),
+        ];
+        for from in &directions {
+            let r_in = Ray::new(-*from, *from);
+            for to in &directions {
+                let scattered = Ray::new(*to, hit_rec.p);
+                let expected = lambertian.eval(&r_in, &hit_rec, &scattered);
+                let value = smooth.eval(&r_in, &hit_rec, &scattered);
+                for i in 0..3 {
+                    assert!(
+                        (value[i] - expected[i]).abs() < 1e-6,
+                        "{value:?} {expected:?}"
+                    );
+                }
+            }
+        }
+
+        // Rough surfaces are darker head on and brighter back towards a
+        // grazing light
+        let r_in = Ray::new(Vec3(0., 0., -1.), Vec3(0., 0., 1.));
+        let head_on = Ray::new(Vec3(0., 0., 1.), hit_rec.p);
+        assert!(
+            rough.eval(&r_in, &hit_rec, &head_on)[0] < smooth.eval(&r_in, &hit_rec, &head_on)[0]
+        );
+        let r_in = Ray::new(Vec3(-3., 0., -0.5), Vec3(3., 0., 0.5));
+        let back = Ray::new(Vec3(3., 0., 0.5), hit_rec.p);
+        assert!(rough.eval(&r_in, &hit_rec, &back)[0] > smooth.eval(&r_in, &hit_rec, &back)[0]);
+    }
+}
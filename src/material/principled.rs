@@ -0,0 +1,317 @@
+//! A single material covering most surfaces, after Burley, "Physically
+//! Based Shading at Disney" (2012)
+
+use super::{
+    microfacet::{DielectricPdf, Ggx, ReflectionPdf},
+    Lobes, Material, ScatterRecord,
+};
+use crate::{
+    color::Color,
+    hittable,
+    pdf::{CosinePdf, Pdf},
+    ray::Ray,
+    texture::Texture,
+    vec3::{onb::Onb, Point3},
+};
+use std::f32::consts::PI;
+
+/// Blends a diffuse base, a specular layer, metal, glass and a clearcoat,
+/// each set by a parameter between zero and one that can vary over the
+/// surface
+pub struct Principled {
+    pub base_color: Box<dyn Texture<Color>>,
+    /// Blends from a dielectric to a metal tinted by the base color
+    pub metallic: Box<dyn Texture<f32>>,
+    /// Roughness of the specular and transmission lobes, below 0.05 raised
+    /// to it
+    pub roughness: Box<dyn Texture<f32>>,
+    /// Reflectance of dielectrics head on, where 0.5 is the four percent of
+    /// glass and plastic. It also sets the refractive index for
+    /// transmission.
+    pub specular: Box<dyn Texture<f32>>,
+    /// A second, glossy and colorless specular lobe on top
+    pub clearcoat: Box<dyn Texture<f32>>,
+    /// Extra reflection towards grazing angles, like cloth
+    pub sheen: Box<dyn Texture<f32>>,
+    /// Blends from an opaque dielectric to glass tinted by the base color
+    pub transmission: Box<dyn Texture<f32>>,
+    /// Light given off by the front of the surface
+    pub emission: Box<dyn Texture<Color>>,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Principled {
+            base_color: Box::new(Color::new(0.8, 0.8, 0.8)),
+            metallic: Box::new(0.),
+            roughness: Box::new(0.5),
+            specular: Box::new(0.5),
+            clearcoat: Box::new(0.),
+            sheen: Box::new(0.),
+            transmission: Box::new(0.),
+            emission: Box::new(Color::black()),
+        }
+    }
+}
+
+/// The parameters at one point of the surface
+struct Parameters {
+    base_color: Color,
+    metallic: f32,
+    distribution: Ggx,
+    roughness: f32,
+    specular: f32,
+    clearcoat: f32,
+    sheen: f32,
+    transmission: f32,
+}
+
+impl Parameters {
+    fn diffuse_weight(&self) -> f32 {
+        (1. - self.metallic) * (1. - self.transmission)
+    }
+
+    fn transmission_weight(&self) -> f32 {
+        (1. - self.metallic) * self.transmission
+    }
+
+    /// Refractive index past the surface over the one on the side of the
+    /// incoming ray, from the index giving the specular reflectance head on
+    fn eta(&self, front_face: bool) -> f32 {
+        let r0 = (0.08 * self.specular).sqrt();
+        let refractive_index = ((1. + r0) / (1. - r0)).max(1.01);
+        if front_face {
+            refractive_index
+        } else {
+            1. / refractive_index
+        }
+    }
+}
+
+/// Roughness of the clearcoat, which is always glossy
+const CLEARCOAT_ROUGHNESS: f32 = 0.1;
+
+impl Principled {
+    fn parameters(&self, p: &Point3) -> Parameters {
+        let roughness = self.roughness.value(p).max(0.05);
+        Parameters {
+            base_color: self.base_color.value(p),
+            metallic: self.metallic.value(p),
+            distribution: Ggx::new(roughness),
+            roughness,
+            specular: self.specular.value(p),
+            clearcoat: self.clearcoat.value(p),
+            sheen: self.sheen.value(p),
+            transmission: self.transmission.value(p),
+        }
+    }
+}
+
+fn schlick_weight(cosine: f32) -> f32 {
+    (1. - cosine).clamp(0., 1.).powi(5)
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, hit_rec: &hittable::HitRecord) -> Option<ScatterRecord> {
+        let parameters = self.parameters(&hit_rec.p);
+        let r_out = -r_in.direction;
+
+        // Sampled in proportion to the weights of the lobes
+        let specular_weight = 1. - parameters.transmission_weight();
+        let lobes: Vec<(f32, Box<dyn Pdf>)> = vec![
+            (
+                parameters.diffuse_weight(),
+                Box::new(CosinePdf::new(&hit_rec.normal)),
+            ),
+            (
+                specular_weight,
+                Box::new(ReflectionPdf::new(
+                    &hit_rec.normal,
+                    &r_out,
+                    parameters.distribution,
+                )),
+            ),
+            (
+                parameters.transmission_weight(),
+                Box::new(DielectricPdf::new(
+                    &hit_rec.normal,
+                    &r_out,
+                    parameters.eta(hit_rec.front_face),
+                    parameters.distribution,
+                )),
+            ),
+            (
+                0.25 * parameters.clearcoat,
+                Box::new(ReflectionPdf::new(
+                    &hit_rec.normal,
+                    &r_out,
+                    Ggx::new(CLEARCOAT_ROUGHNESS),
+                )),
+            ),
+        ];
+        Some(ScatterRecord::Pdf(Box::new(Lobes::new(lobes))))
+    }
+
+    fn eval(&self, r_in: &Ray, hit_rec: &hittable::HitRecord, scattered: &Ray) -> Color {
+        let parameters = self.parameters(&hit_rec.p);
+        let frame = Onb::new(&hit_rec.normal);
+        let wo = frame.local(&-r_in.direction.normalize());
+        let wi = frame.local(&scattered.direction.normalize());
+        let base_color = r_in.reflectance(&parameters.base_color);
+        let white = Color::new(1., 1., 1.);
+        let mut f = Color::black();
+
+        if wo.2 > 0. && wi.2 > 0. {
+            let cos_d = wi.dot(&(wo + wi).normalize());
+
+            // Burley's diffuse, which gets brighter towards grazing angles
+            // on rough surfaces and darker on smooth ones
+            let diffuse_weight = parameters.diffuse_weight();
+            if diffuse_weight > 0. {
+                let f90 = 0.5 + 2. * parameters.roughness * cos_d * cos_d;
+                let retro = |cosine: f32| 1. + (f90 - 1.) * schlick_weight(cosine);
+                f += base_color.clone() * (diffuse_weight * retro(wo.2) * retro(wi.2) * wi.2 / PI);
+                f += white.clone()
+                    * (diffuse_weight * parameters.sheen * schlick_weight(cos_d) * wi.2);
+            }
+
+            // Dielectrics reflect white light, metals their own color
+            let f0 = white.clone() * (0.08 * parameters.specular * (1. - parameters.metallic))
+                + base_color.clone() * parameters.metallic;
+            let channel = |i: usize| f0[i] + (1. - f0[i]) * schlick_weight(cos_d);
+            let fresnel = Color::new(channel(0), channel(1), channel(2));
+            let specular_weight = 1. - parameters.transmission_weight();
+            f += fresnel * (specular_weight * parameters.distribution.reflection(&wo, &wi));
+
+            let clearcoat_fresnel = 0.04 + 0.96 * schlick_weight(cos_d);
+            let clearcoat = Ggx::new(CLEARCOAT_ROUGHNESS).reflection(&wo, &wi);
+            f += white.clone() * (0.25 * parameters.clearcoat * clearcoat_fresnel * clearcoat);
+        }
+
+        let transmission_weight = parameters.transmission_weight();
+        if transmission_weight > 0. {
+            let eta = parameters.eta(hit_rec.front_face);
+            let value = transmission_weight * parameters.distribution.dielectric(&wo, &wi, eta);
+            // Only light going through the glass takes on its color
+            f += if wi.2 < 0. { base_color } else { white } * value;
+        }
+        f
+    }
+
+    fn emitted(&self, r_in: &Ray, hit_rec: &hittable::HitRecord) -> Color {
+        if hit_rec.front_face {
+            r_in.illuminant(&self.emission.value(&hit_rec.p))
+        } else {
+            Color::black()
+        }
+    }
+
+    fn albedo(&self, hit_rec: &hittable::HitRecord) -> Color {
+        self.base_color.value(&hit_rec.p)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{pdf::SpherePdf, texture::Checker, vec3::Vec3};
+
+    fn hit_rec(material: &Principled) -> hittable::HitRecord<'_> {
+        hittable::HitRecord::from_outside(Vec3(0., 0., 0.), Vec3(0., 0., 1.), material)
+    }
+
+    /// Light reflected and transmitted towards `r_in`, estimated by sampling
+    /// the material and by sampling every direction uniformly
+    fn albedo(material: &Principled, r_in: &Ray) -> (Color, Color) {
+        let hit_rec = hit_rec(material);
+        let Some(ScatterRecord::Pdf(pdf)) = material.scatter(r_in, &hit_rec) else {
+            panic!("principled materials sample a pdf");
+        };
+        let n = 100000;
+        let estimate = |pdf: &dyn Pdf| {
+            let mut total = Color::black();
+            for _ in 0..n {
+                let scattered = Ray::new(pdf.generate(), hit_rec.p);
+                let pdf_value = pdf.value(&scattered.direction);
+                if pdf_value > 0. {
+                    total += material.eval(r_in, &hit_rec, &scattered) * (1. / pdf_value);
+                }
+            }
+            total * (1. / n as f32)
+        };
+        (estimate(pdf.as_ref()), estimate(&SpherePdf))
+    }
+
+    fn assert_close(a: &Color, b: &Color, tolerance: f32) {
+        for channel in 0..3 {
+            assert!((a[channel] - b[channel]).abs() < tolerance, "{a:?} {b:?}");
+        }
+    }
+
+    #[test]
+    fn lobes_are_sampled_with_their_density() {
+        let r_in = Ray::new(Vec3(0.5, 0.2, -1.), Vec3(-0.5, -0.2, 1.));
+        let materials = [
+            Principled {
+                base_color: Box::new(Color::new(0.8, 0.3, 0.1)),
+                roughness: Box::new(0.6),
+                clearcoat: Box::new(1.),
+                sheen: Box::new(1.),
+                ..Default::default()
+            },
+            Principled {
+                metallic: Box::new(0.5),
+                roughness: Box::new(0.7),
+                ..Default::default()
+            },
+            Principled {
+                transmission: Box::new(0.7),
+                roughness: Box::new(0.7),
+                ..Default::default()
+            },
+        ];
+        for material in &materials {
+            let (sampled, uniform) = albedo(material, &r_in);
+            assert_close(&sampled, &uniform, 0.04);
+        }
+    }
+
+    #[test]
+    fn metal_and_glass_keep_their_energy() {
+        let r_in = Ray::new(Vec3(0.3, 0., -1.), Vec3(-0.3, 0., 1.));
+        let white_metal = Principled {
+            base_color: Box::new(Color::new(1., 1., 1.)),
+            metallic: Box::new(1.),
+            roughness: Box::new(0.3),
+            ..Default::default()
+        };
+        let (reflected, _) = albedo(&white_metal, &r_in);
+        assert!(reflected[0] <= 1. && reflected[0] > 0.9, "{reflected:?}");
+
+        let clear_glass = Principled {
+            base_color: Box::new(Color::new(1., 1., 1.)),
+            transmission: Box::new(1.),
+            roughness: Box::new(0.2),
+            ..Default::default()
+        };
+        let (total, _) = albedo(&clear_glass, &r_in);
+        assert!(total[0] <= 1.01 && total[0] > 0.9, "{total:?}");
+    }
+
+    #[test]
+    fn parameters_follow_textures() {
+        let material = Principled {
+            base_color: Box::new(Checker {
+                even: Color::new(1., 0., 0.),
+                odd: Color::new(0., 0., 1.),
+                size: 1.,
+            }),
+            emission: Box::new(Color::new(2., 2., 2.)),
+            ..Default::default()
+        };
+        let hit_rec = hit_rec(&material);
+        assert_eq!(material.albedo(&hit_rec), Color::new(1., 0., 0.));
+        let r_in = Ray::new(Vec3(0., 0., -1.), Vec3(0., 0., 1.));
+        assert_eq!(material.emitted(&r_in, &hit_rec), Color::new(2., 2., 2.));
+    }
+}
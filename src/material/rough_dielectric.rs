@@ -0,0 +1,110 @@
+//! Frosted glass, whose rough surface blurs both what it reflects and what
+//! is seen through it
+
+use super::{
+    microfacet::{DielectricPdf, Ggx},
+    refract_or_reflect, Fresnel, Material, ScatterRecord,
+};
+use crate::{color::Color, hittable, ray::Ray, vec3::onb::Onb};
+
+/// Dielectric with a GGX distribution of microfacets, each reflecting or
+/// refracting like the smooth `Dielectric` does
+pub struct RoughDielectric {
+    refractive_index: f32,
+    distribution: Ggx,
+}
+
+impl RoughDielectric {
+    pub fn new(refractive_index: f32, distribution: Ggx) -> Self {
+        RoughDielectric {
+            refractive_index,
+            distribution,
+        }
+    }
+
+    /// Refractive index past the surface over the one on the side of the
+    /// incoming ray
+    fn eta(&self, hit_rec: &hittable::HitRecord) -> f32 {
+        if hit_rec.front_face {
+            self.refractive_index
+        } else {
+            1. / self.refractive_index
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, r_in: &Ray, hit_rec: &hittable::HitRecord) -> Option<ScatterRecord> {
+        if self.distribution.is_smooth() {
+            let direction =
+                refract_or_reflect(r_in, hit_rec, self.refractive_index, Fresnel::Exact);
+            return Some(ScatterRecord::Specular {
+                attenuation: Color::new(1., 1., 1.),
+                ray: r_in.spawn(direction, hit_rec.p),
+            });
+        }
+        Some(ScatterRecord::Pdf(Box::new(DielectricPdf::new(
+            &hit_rec.normal,
+            &-r_in.direction,
+            self.eta(hit_rec),
+            self.distribution,
+        ))))
+    }
+
+    fn eval(&self, r_in: &Ray, hit_rec: &hittable::HitRecord, scattered: &Ray) -> Color {
+        if self.distribution.is_smooth() {
+            return Color::black();
+        }
+        let frame = Onb::new(&hit_rec.normal);
+        let wo = frame.local(&-r_in.direction.normalize());
+        let wi = frame.local(&scattered.direction.normalize());
+        Color::new(1., 1., 1.) * self.distribution.dielectric(&wo, &wi, self.eta(hit_rec))
+    }
+
+    fn albedo(&self, _: &hittable::HitRecord) -> Color {
+        Color::new(1., 1., 1.)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn frosted_glass_scatters_to_both_sides() {
+        let glass = RoughDielectric::new(1.5, Ggx::new(0.4));
+        let hit_rec = hittable::HitRecord::from_outside(Vec3(0., 0., 0.), Vec3(0., 0., 1.), &glass);
+        let r_in = Ray::new(Vec3(0.2, 0., -1.), Vec3(-0.2, 0., 1.));
+        let Some(ScatterRecord::Pdf(pdf)) = glass.scatter(&r_in, &hit_rec) else {
+            panic!("rough glass samples a pdf");
+        };
+
+        let n = 20000;
+        let (mut reflected, mut transmitted) = (0., 0.);
+        for _ in 0..n {
+            let scattered = Ray::new(pdf.generate(), hit_rec.p);
+            let pdf_value = pdf.value(&scattered.direction);
+            let weight = glass.eval(&r_in, &hit_rec, &scattered)[0] / pdf_value / n as f32;
+            if scattered.direction[2] > 0. {
+                reflected += weight;
+            } else {
+                transmitted += weight;
+            }
+        }
+        // Close to the four percent a smooth surface reflects head on
+        assert!((0.03..0.08).contains(&reflected), "{reflected}");
+        assert!(transmitted > 0.85 && reflected + transmitted <= 1.);
+    }
+
+    #[test]
+    fn smooth_limit_is_specular() {
+        let glass = RoughDielectric::new(1.5, Ggx::new(0.));
+        let hit_rec = hittable::HitRecord::from_outside(Vec3(0., 0., 0.), Vec3(0., 0., 1.), &glass);
+        let r_in = Ray::new(Vec3(0., 0., -1.), Vec3(0., 0., 1.));
+        assert!(matches!(
+            glass.scatter(&r_in, &hit_rec),
+            Some(ScatterRecord::Specular { .. })
+        ));
+    }
+}
@@ -0,0 +1,178 @@
+//! Translucent materials like wax, marble and skin, where light wanders
+//! under the surface before leaving it
+
+use super::{
+    medium::{Flight, Medium},
+    refract_or_reflect, Fresnel, Material, ScatterRecord,
+};
+use crate::{color::Color, hittable, ray::Ray};
+
+/// A medium that scatters and absorbs light, behind a smooth dielectric
+/// surface. Light goes on a random walk from where it enters to where it
+/// leaves: between two hits on the inside of the surface, it may instead
+/// scatter somewhere on the way through the `Medium`.
+///
+/// Surfaces must enclose the medium. Entering counts as a bounce, but the
+/// steps of the walk after it are `ScatterRecord::Walk`, so long walks are
+/// not cut short by the bounce limit of the path tracer.
+pub struct Subsurface {
+    refractive_index: f32,
+    medium: Medium,
+}
+
+impl Subsurface {
+    pub fn new(refractive_index: f32, medium: Medium) -> Self {
+        Subsurface {
+            refractive_index,
+            medium,
+        }
+    }
+
+    /// A medium where light travels `mean_free_path` on average before
+    /// something happens to it, which is scattering with a probability of
+    /// `albedo` and being absorbed otherwise
+    pub fn from_mean_free_path(
+        refractive_index: f32,
+        albedo: Color,
+        mean_free_path: Color,
+    ) -> Self {
+        Subsurface::new(
+            refractive_index,
+            Medium::from_mean_free_path(albedo, mean_free_path),
+        )
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, r_in: &Ray, hit_rec: &hittable::HitRecord) -> Option<ScatterRecord> {
+        if hit_rec.front_face {
+            let direction =
+                refract_or_reflect(r_in, hit_rec, self.refractive_index, Fresnel::Exact);
+            return Some(ScatterRecord::Specular {
+                attenuation: Color::new(1., 1., 1.),
+                ray: r_in.spawn(direction, hit_rec.p),
+            });
+        }
+
+        // Inside, since the ray entered or last scattered
+        let (attenuation, ray) = match self.medium.flight(r_in, hit_rec.t) {
+            Flight::Scattered { attenuation, ray } => (attenuation, ray),
+            // Leaving, or reflected back in by the surface
+            Flight::Through { attenuation, ray } => {
+                let direction =
+                    refract_or_reflect(r_in, hit_rec, self.refractive_index, Fresnel::Exact);
+                (attenuation, Ray { direction, ..ray })
+            }
+        };
+        Some(ScatterRecord::Walk { attenuation, ray })
+    }
+
+    /// Share of the light scattered rather than absorbed, each time
+    /// something happens to it
+    fn albedo(&self, _: &hittable::HitRecord) -> Color {
+        self.medium.albedo()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        hittable::{Hittable, HittableList},
+        integrator::{path::PathTracer, Integrator, Scene},
+        sky::Gradient,
+        sphere::Sphere,
+        vec3::Vec3,
+    };
+
+    /// Average of the light leaving a sphere of the medium, for rays
+    /// entering it from above
+    fn walk(medium: Subsurface) -> Color {
+        let sphere = Sphere::new(Vec3(0., 0., 0.), 0.5, medium);
+        let n = 100000;
+        let mut total = Color::black();
+        for _ in 0..n {
+            let mut r = Ray::new(Vec3(0.3, -1., 0.1), Vec3(-0.3, 1., 0.));
+            let mut throughput = Color::new(1., 1., 1.);
+            for _ in 0..10000 {
+                let Some(rec) = sphere.hit(&r, &(0.001..f32::INFINITY)) else {
+                    total += throughput * (1. / n as f32);
+                    break;
+                };
+                let (Some(ScatterRecord::Specular { attenuation, ray })
+                | Some(ScatterRecord::Walk { attenuation, ray })) = rec.material.scatter(&r, &rec)
+                else {
+                    break;
+                };
+                throughput = throughput * attenuation;
+                r = ray;
+            }
+        }
+        total
+    }
+
+    #[test]
+    fn walks_lose_only_absorbed_light() {
+        let white = walk(Subsurface::from_mean_free_path(
+            1.3,
+            Color::new(1., 1., 1.),
+            Color::new(0.1, 0.2, 0.4),
+        ));
+        // Gray media go as far at every wavelength, so no channel is
+        // weighted up
+        let gray = walk(Subsurface::from_mean_free_path(
+            1.3,
+            Color::new(1., 1., 1.),
+            Color::new(0.2, 0.2, 0.2),
+        ));
+        for i in 0..3 {
+            assert!((white[i] - 1.).abs() < 0.02, "{white:?}");
+            assert!((gray[i] - 1.).abs() < 1e-3, "{gray:?}");
+        }
+
+        let skin = walk(Subsurface::from_mean_free_path(
+            1.3,
+            Color::new(0.99, 0.9, 0.8),
+            Color::new(0.1, 0.1, 0.1),
+        ));
+        assert!(skin[0] > skin[1] && skin[1] > skin[2], "{skin:?}");
+        assert!(skin[2] > 0.04 && skin[0] < 1., "{skin:?}");
+    }
+
+    #[test]
+    fn walks_count_as_one_bounce() {
+        let mut world = HittableList::default();
+        world.push(Sphere::new(
+            Vec3(0., 0., 0.),
+            0.5,
+            Subsurface::from_mean_free_path(
+                1.3,
+                Color::new(1., 1., 1.),
+                Color::new(0.05, 0.05, 0.05),
+            ),
+        ));
+        let lights = HittableList::default();
+        let background = Gradient::new(Color::new(1., 1., 1.), Color::new(1., 1., 1.));
+        let scene = Scene {
+            world: &world,
+            lights: &lights,
+            background: &background,
+        };
+        // Entering and leaving are enough, however long the walk
+        let integrator = PathTracer {
+            max_depth: 2,
+            ..PathTracer::default()
+        };
+
+        let n = 20000;
+        let mut total = 0.;
+        for _ in 0..n {
+            let r = Ray::new(Vec3(0.1, -1., 0.), Vec3(0., 2., 0.));
+            let (color, bounces) = integrator.ray_color(r, &scene);
+            assert!(bounces <= 2);
+            total += color[0] / n as f32;
+        }
+        // A white furnace, where nothing absorbs
+        assert!((total - 1.).abs() < 0.01, "{total}");
+    }
+}
@@ -0,0 +1,228 @@
+//! Films a few hundred nanometers thick, like soap or oil, whose reflections
+//! off their two sides interfere and color the light differently at each
+//! wavelength
+
+use crate::{color::Color, ray::Ray, spectrum::RGB_WAVELENGTHS, texture::Texture, vec3::Point3};
+use std::{
+    f32::consts::PI,
+    ops::{Add, Div, Mul, Sub},
+};
+
+/// A clear film over a surface, reflecting light from its top and bottom
+pub struct ThinFilm {
+    pub refractive_index: f32,
+    /// In nanometers
+    pub thickness: Box<dyn Texture<f32>>,
+}
+
+impl ThinFilm {
+    /// Fraction of light reflected at each wavelength of `r_in`, arriving
+    /// at `cosine` to the normal from a medium of refractive index
+    /// `outside`. `substrate` gives the complex refractive index of what is
+    /// under the film at a wavelength.
+    pub fn reflectance(
+        &self,
+        r_in: &Ray,
+        p: &Point3,
+        cosine: f32,
+        outside: f32,
+        substrate: impl Fn(f32) -> (f32, f32),
+    ) -> Color {
+        let thickness = self.thickness.value(p).max(0.);
+        let channel = |i: usize| {
+            let lambda = match &r_in.wavelengths {
+                Some(wavelengths) => wavelengths.0[i],
+                None => RGB_WAVELENGTHS[i],
+            };
+            let (eta, k) = substrate(lambda);
+            let indices = [
+                Complex::real(outside),
+                Complex::real(self.refractive_index),
+                Complex { re: eta, im: k },
+            ];
+            // The index times the cosine of the angle to the normal in each
+            // layer, following Snell's law. It turns imaginary past the
+            // critical angle and in metals, where the light dies out.
+            let sin_squared = outside * outside * (1. - cosine * cosine);
+            let q = indices.map(|n| (n * n - Complex::real(sin_squared)).sqrt());
+            // A round trip through the film shifts the phase and, when the
+            // light does not propagate in it, weakens it
+            let delta = q[1] * Complex::real(4. * PI * thickness / lambda);
+            let round_trip = Complex {
+                re: delta.re.cos(),
+                im: delta.re.sin(),
+            } * Complex::real((-delta.im).exp());
+
+            // Reflected amplitudes summed over every bounce in the film, for
+            // each polarization
+            let airy = |r12: Complex, r23: Complex| {
+                ((r12 + r23 * round_trip) / (Complex::real(1.) + r12 * r23 * round_trip))
+                    .norm_squared()
+            };
+            let perpendicular = |a: usize, b: usize| (q[a] - q[b]) / (q[a] + q[b]);
+            let parallel = |a: usize, b: usize| {
+                let (na2, nb2) = (indices[a] * indices[a], indices[b] * indices[b]);
+                (nb2 * q[a] - na2 * q[b]) / (nb2 * q[a] + na2 * q[b])
+            };
+            let reflectance = (airy(perpendicular(0, 1), perpendicular(1, 2))
+                + airy(parallel(0, 1), parallel(1, 2)))
+                / 2.;
+            reflectance.clamp(0., 1.)
+        };
+        Color::new(channel(0), channel(1), channel(2))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    fn real(re: f32) -> Self {
+        Complex { re, im: 0. }
+    }
+
+    fn norm_squared(self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// The root with a non-negative real part, and a non-negative imaginary
+    /// part on the negative real axis
+    fn sqrt(self) -> Self {
+        let norm = self.norm_squared().sqrt();
+        let re = ((norm + self.re) / 2.).max(0.).sqrt();
+        let im = ((norm - self.re) / 2.).max(0.).sqrt();
+        Complex {
+            re,
+            im: if self.im < 0. { -im } else { im },
+        }
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex {
+            re: self.re + rhs.re,
+            im: self.im + rhs.im,
+        }
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex {
+            re: self.re - rhs.re,
+            im: self.im - rhs.im,
+        }
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex {
+            re: self.re * rhs.re - self.im * rhs.im,
+            im: self.re * rhs.im + self.im * rhs.re,
+        }
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, rhs: Complex) -> Complex {
+        let norm = rhs.norm_squared();
+        Complex {
+            re: (self.re * rhs.re + self.im * rhs.im) / norm,
+            im: (self.im * rhs.re - self.re * rhs.im) / norm,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        material::{conductor::ComplexIor, Fresnel},
+        vec3::Vec3,
+    };
+
+    fn r_in() -> Ray {
+        Ray::new(Vec3(0., 0., -1.), Vec3(0., 0., 1.))
+    }
+
+    #[test]
+    fn vanishing_films_leave_the_surface_alone() {
+        let p = Vec3(0., 0., 0.);
+        let none = ThinFilm {
+            refractive_index: 1.8,
+            thickness: Box::new(0.),
+        };
+        for cosine in [1., 0.6, 0.1] {
+            let glass = none.reflectance(&r_in(), &p, cosine, 1., |_| (1.5, 0.));
+            let expected = Fresnel::Exact.reflectance(cosine, 1.5);
+            // Leaving the glass, including total internal reflection
+            let inside = none.reflectance(&r_in(), &p, cosine, 1.5, |_| (1., 0.));
+            let expected_inside = Fresnel::Exact.reflectance(cosine, 1. / 1.5);
+            let gold = none.reflectance(&r_in(), &p, cosine, 1., |lambda| {
+                ComplexIor::GOLD.at(lambda)
+            });
+            let expected_gold = ComplexIor::GOLD.reflectance(cosine);
+            for i in 0..3 {
+                assert!((glass[i] - expected).abs() < 1e-4, "{glass:?} {expected}");
+                assert!((inside[i] - expected_inside).abs() < 1e-4, "{inside:?}");
+                assert!((gold[i] - expected_gold[i]).abs() < 1e-4, "{gold:?}");
+            }
+        }
+
+        // A film matching the glass is just more glass
+        let matching = ThinFilm {
+            refractive_index: 1.5,
+            thickness: Box::new(300.),
+        };
+        let glass = matching.reflectance(&r_in(), &p, 0.7, 1., |_| (1.5, 0.));
+        assert!((glass[1] - Fresnel::Exact.reflectance(0.7, 1.5)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn quarter_wave_coatings_cancel_reflections() {
+        let p = Vec3(0., 0., 0.);
+        let green = RGB_WAVELENGTHS[1];
+        let refractive_index = 1.5f32.sqrt();
+        let coating = ThinFilm {
+            refractive_index,
+            thickness: Box::new(green / (4. * refractive_index)),
+        };
+        let glass = coating.reflectance(&r_in(), &p, 1., 1., |_| (1.5, 0.));
+        assert!(glass[1] < 1e-5, "{glass:?}");
+        // Other wavelengths are out of phase, giving lenses their tint
+        assert!(glass[0] > 1e-4 && glass[2] > 1e-4, "{glass:?}");
+
+        // A bubble half a wavelength thick cancels its reflections, as only
+        // the one off the outside flips phase
+        let half_wave = ThinFilm {
+            refractive_index: 1.33,
+            thickness: Box::new(green / (2. * 1.33)),
+        };
+        let bubble = half_wave.reflectance(&r_in(), &p, 1., 1., |_| (1., 0.));
+        assert!(bubble[1] < 1e-5, "{bubble:?}");
+        let quarter_wave = ThinFilm {
+            refractive_index: 1.33,
+            thickness: Box::new(green / (4. * 1.33)),
+        };
+        let bubble = quarter_wave.reflectance(&r_in(), &p, 1., 1., |_| (1., 0.));
+        // Four times the reflectance of a single surface, nearly
+        let single = Fresnel::Exact.reflectance(1., 1.33);
+        assert!(
+            bubble[1] > 3.5 * single && bubble[1] < 4. * single,
+            "{bubble:?}"
+        );
+    }
+}
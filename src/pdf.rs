@@ -0,0 +1,173 @@
+use crate::{
+    hittable::Hittable,
+    vec3::{onb::Onb, Point3, Vec3},
+};
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// A distribution of directions that can be both sampled and evaluated
+pub trait Pdf {
+    /// Density, with respect to solid angle, of generating `direction`
+    fn value(&self, direction: &Vec3) -> f32;
+
+    /// Random direction drawn from the distribution
+    fn generate(&self) -> Vec3;
+}
+
+/// Uniform over all directions
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _: &Vec3) -> f32 {
+        1. / (4. * PI)
+    }
+
+    fn generate(&self) -> Vec3 {
+        Vec3::random_unit_vector()
+    }
+}
+
+/// Proportional to the cosine with a normal, the ideal distribution for a
+/// lambertian surface
+pub struct CosinePdf {
+    uvw: Onb,
+    normal: Vec3,
+}
+
+impl CosinePdf {
+    pub fn new(normal: &Vec3) -> Self {
+        CosinePdf {
+            uvw: Onb::new(normal),
+            normal: normal.normalize(),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f32 {
+        let cosine = direction.normalize().dot(&self.normal);
+        (cosine / PI).max(0.)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.uvw.transform(&Vec3::random_cosine_direction())
+    }
+}
+
+/// Directions from `origin` towards a hittable, usually the light list
+pub struct HittablePdf<'a, H: Hittable> {
+    objects: &'a H,
+    origin: Point3,
+}
+
+impl<'a, H: Hittable> HittablePdf<'a, H> {
+    pub fn new(objects: &'a H, origin: Point3) -> Self {
+        HittablePdf { objects, origin }
+    }
+}
+
+impl<H: Hittable> Pdf for HittablePdf<'_, H> {
+    fn value(&self, direction: &Vec3) -> f32 {
+        self.objects.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.objects.random(&self.origin)
+    }
+}
+
+/// Picks one of its distributions with equal probability
+pub struct MixturePdf<'a> {
+    pdfs: Vec<&'a dyn Pdf>,
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(pdfs: Vec<&'a dyn Pdf>) -> Self {
+        MixturePdf { pdfs }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f32 {
+        let weight = 1. / (self.pdfs.len() as f32);
+        self.pdfs
+            .iter()
+            .map(|pdf| weight * pdf.value(direction))
+            .sum()
+    }
+
+    fn generate(&self) -> Vec3 {
+        let index = rand::thread_rng().gen_range(0..self.pdfs.len());
+        self.pdfs[index].generate()
+    }
+}
+
+/// Weight of a sample drawn with density `pdf` when another strategy could
+/// have produced it with density `other_pdf`, from Veach's power heuristic
+/// with an exponent of two
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0. {
+        a / (a + b)
+    } else {
+        0.
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Monte Carlo estimate of the integral of a density over the sphere,
+    /// which has to be one
+    fn integrate(pdf: &dyn Pdf) -> f32 {
+        let n = 20000;
+        let sphere = SpherePdf;
+        (0..n)
+            .map(|_| {
+                let dir = sphere.generate();
+                pdf.value(&dir) / sphere.value(&dir)
+            })
+            .sum::<f32>()
+            / (n as f32)
+    }
+
+    #[test]
+    fn cosine_pdf_integrates_to_one() {
+        let pdf = CosinePdf::new(&Vec3(0.2, 1., -0.3));
+
+        assert!((integrate(&pdf) - 1.).abs() < 0.05);
+    }
+
+    #[test]
+    fn cosine_pdf_generates_in_hemisphere() {
+        let normal = Vec3(0.2, 1., -0.3);
+        let pdf = CosinePdf::new(&normal);
+        for _ in 0..100 {
+            let dir = pdf.generate();
+            assert!(dir.dot(&normal) >= 0.);
+            assert!(pdf.value(&dir) >= 0.);
+        }
+    }
+
+    #[test]
+    fn mixture_pdf_averages() {
+        let cosine = CosinePdf::new(&Vec3(0., 1., 0.));
+        let sphere = SpherePdf;
+        let mixture = MixturePdf::new(vec![&cosine, &sphere]);
+        let dir = Vec3(0., 1., 0.);
+        let expected = 0.5 * (1. / PI) + 0.5 * (1. / (4. * PI));
+
+        assert!((mixture.value(&dir) - expected).abs() < 1e-6);
+        assert!((integrate(&mixture) - 1.).abs() < 0.05);
+    }
+
+    #[test]
+    fn power_heuristic_weights_sum_to_one() {
+        for (a, b) in [(1., 1.), (0.3, 4.), (10., 0.)] {
+            let sum = power_heuristic(a, b) + power_heuristic(b, a);
+            assert!((sum - 1.).abs() < 1e-6);
+        }
+        assert_eq!(power_heuristic(0., 0.), 0.);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{
+    hittable,
+    material::Material,
+    ray::Ray,
+    vec3::{self, Point3, Vec3},
+};
+use rand::Rng;
+use std::ops::Range;
+
+/// Parallelogram with a corner at `q` and edges `u` and `v`
+pub struct Quad<M: Material> {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    normal: Vec3,
+    d: f32,
+    area: f32,
+    material: M,
+}
+
+impl<M: Material> Quad<M> {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: M) -> Self {
+        let n = u.cross(&v);
+        let normal = n.normalize();
+        Quad {
+            q,
+            u,
+            v,
+            w: n / n.dot(&n),
+            normal,
+            d: normal.dot(&q),
+            area: n.magnitude(),
+            material,
+        }
+    }
+}
+
+impl<M: Material> hittable::Hittable for Quad<M> {
+    fn hit(&self, r: &Ray, ray_interval: &Range<f32>) -> Option<hittable::HitRecord<'_>> {
+        let denom = self.normal.dot(&r.direction);
+        // Parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(&r.origin)) / denom;
+        if !ray_interval.contains(&t) {
+            return None;
+        }
+
+        // Planar coordinates of the hit along the edges
+        let p = r.at(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0f32..=1.).contains(&alpha) || !(0f32..=1.).contains(&beta) {
+            return None;
+        }
+
+        Some(hittable::HitRecord::new(
+            p,
+            self.normal,
+            t,
+            r,
+            &self.material,
+        ))
+    }
+
+    fn pdf_value(&self, origin: &vec3::Point3, direction: &vec3::Vec3) -> f32 {
+        match self.hit(&Ray::new(*direction, *origin), &(0.001..f32::INFINITY)) {
+            Some(rec) => {
+                let dist_squared = rec.t * rec.t * direction.magnitude_squared();
+                let cosine = (direction.dot(&rec.normal) / direction.magnitude()).abs();
+                dist_squared / (cosine * self.area)
+            }
+            None => 0.,
+        }
+    }
+
+    fn random(&self, origin: &vec3::Point3) -> vec3::Vec3 {
+        let mut rng = rand::thread_rng();
+        let p = self.q + (rng.gen::<f32>() * self.u) + (rng.gen::<f32>() * self.v);
+        p - origin
+    }
+
+    fn sample_surface(&self) -> Option<(hittable::HitRecord<'_>, f32)> {
+        let mut rng = rand::thread_rng();
+        let p = self.q + (rng.gen::<f32>() * self.u) + (rng.gen::<f32>() * self.v);
+        Some((
+            hittable::HitRecord::from_outside(p, self.normal, &self.material),
+            1. / self.area,
+        ))
+    }
+
+    fn surface_pdf_value(&self, origin: &vec3::Point3, direction: &vec3::Vec3) -> f32 {
+        match self.hit(&Ray::new(*direction, *origin), &(0.001..f32::INFINITY)) {
+            Some(_) => 1. / self.area,
+            None => 0.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{color::Color, hittable::Hittable, material::Lambertian};
+
+    fn unit_quad() -> Quad<Lambertian> {
+        Quad::new(
+            Vec3(-0.5, -0.5, -1.),
+            Vec3(1., 0., 0.),
+            Vec3(0., 1., 0.),
+            Lambertian::new(Color::black()),
+        )
+    }
+
+    #[test]
+    fn quad_hit() {
+        let quad = unit_quad();
+        let ray = Ray::new(Vec3(0.2, 0.3, -1.), Vec3(0., 0., 0.));
+        let rec = quad.hit(&ray, &(0f32..10f32));
+
+        assert!(rec.is_some());
+        assert_eq!(rec.unwrap().t, 1.);
+    }
+
+    #[test]
+    fn quad_miss_outside_edges() {
+        let quad = unit_quad();
+        let ray = Ray::new(Vec3(0.6, 0., -1.), Vec3(0., 0., 0.));
+
+        assert!(quad.hit(&ray, &(0f32..10f32)).is_none());
+    }
+
+    #[test]
+    fn quad_pdf_value_head_on() {
+        // Straight on at distance one, the density is 1 / area
+        let quad = Quad::new(
+            Vec3(-1., -1., -1.),
+            Vec3(2., 0., 0.),
+            Vec3(0., 2., 0.),
+            Lambertian::new(Color::black()),
+        );
+        let pdf = quad.pdf_value(&Vec3(0., 0., 0.), &Vec3(0., 0., -1.));
+
+        assert!((pdf - 0.25).abs() < 1e-5);
+    }
+
+    #[test]
+    fn quad_random_hits_quad() {
+        let quad = unit_quad();
+        let origin = Vec3(0., 0., 0.);
+        for _ in 0..100 {
+            let dir = quad.random(&origin);
+            assert!(quad.hit(&Ray::new(dir, origin), &(0f32..10f32)).is_some());
+        }
+    }
+
+    #[test]
+    fn quad_sample_surface_on_quad() {
+        let quad = unit_quad();
+        for _ in 0..100 {
+            let (rec, pdf) = quad.sample_surface().unwrap();
+            assert!((rec.p.2 + 1.).abs() < 1e-5);
+            assert!(rec.p.0.abs() <= 0.5 && rec.p.1.abs() <= 0.5);
+            assert_eq!(rec.normal, Vec3(0., 0., 1.));
+            assert!((pdf - 1.).abs() < 1e-5);
+        }
+    }
+}
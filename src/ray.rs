@@ -1,13 +1,83 @@
-use crate::vec3;
+use crate::{color::Color, spectrum::Wavelengths, vec3};
+use rand::Rng;
 
 pub struct Ray {
     pub direction: vec3::Vec3,
     pub origin: vec3::Point3,
+    /// Wavelengths the ray carries light at in spectral mode. Colors along
+    /// the path then hold one value per wavelength instead of RGB.
+    pub wavelengths: Option<Wavelengths>,
+    /// Channel of the colors, or wavelength in spectral mode, that steps
+    /// of the path depending on the wavelength are sampled for: the hero
+    /// wavelength. Picked at the first such step.
+    pub hero: Option<usize>,
+    /// Density of the path at each channel over those steps, up to a
+    /// common scale
+    pub densities: [f32; 3],
 }
 
 impl Ray {
     pub fn new(direction: vec3::Vec3, origin: vec3::Vec3) -> Self {
-        Ray { direction, origin }
+        Ray {
+            direction,
+            origin,
+            wavelengths: None,
+            hero: None,
+            densities: [1.; 3],
+        }
+    }
+
+    /// A new ray carrying the same wavelengths and hero, for continuing a
+    /// path
+    pub fn spawn(&self, direction: vec3::Vec3, origin: vec3::Point3) -> Self {
+        Ray {
+            direction,
+            origin,
+            wavelengths: self.wavelengths,
+            hero: self.hero,
+            densities: self.densities,
+        }
+    }
+
+    /// The hero, picking one if the path has none yet. The wavelengths of
+    /// spectral mode are drawn at random, so the first one serves.
+    pub fn hero(&self) -> usize {
+        match (self.hero, &self.wavelengths) {
+            (Some(hero), _) => hero,
+            (None, Some(_)) => 0,
+            (None, None) => rand::thread_rng().gen_range(0..3),
+        }
+    }
+
+    /// Weight of a step that depends on the wavelength, sampled for `hero`,
+    /// given what the step lets through and its density at each channel.
+    /// Every channel is weighted against the whole path having been sampled
+    /// for the others instead, with the balance heuristic, and `next`
+    /// carries on the densities.
+    pub fn hero_weight(&self, next: &mut Ray, hero: usize, value: &Color, pdf: [f32; 3]) -> Color {
+        let densities = [0, 1, 2].map(|i| self.densities[i] * pdf[i]);
+        let total = densities.iter().sum::<f32>();
+        // Kept from growing or shrinking out of range over long paths
+        let max = densities.iter().fold(0f32, |a, b| a.max(*b));
+        next.hero = Some(hero);
+        next.densities = densities.map(|density| density / max);
+        value.clone() * (self.densities.iter().sum::<f32>() / total)
+    }
+
+    /// An RGB reflectance as carried by the ray
+    pub fn reflectance(&self, rgb: &Color) -> Color {
+        match &self.wavelengths {
+            Some(wavelengths) => wavelengths.reflectance(rgb),
+            None => rgb.clone(),
+        }
+    }
+
+    /// An RGB light as carried by the ray
+    pub fn illuminant(&self, rgb: &Color) -> Color {
+        match &self.wavelengths {
+            Some(wavelengths) => wavelengths.illuminant(rgb),
+            None => rgb.clone(),
+        }
     }
 
     pub fn at(&self, t: f32) -> vec3::Point3 {
@@ -26,8 +96,24 @@ mod test {
         let arg = 50f32;
         let expected = (50f32, 50f32, 50f32);
 
-        let input = Ray { direction, origin };
+        let input = Ray::new(direction, origin);
 
         assert_eq!(input.at(arg), vec3::Point3::from(expected));
     }
+
+    #[test]
+    fn hero_weights_cover_the_whole_path() {
+        let r = Ray::new(vec3::Vec3(0., 0., 1.), vec3::Point3::from((0., 0., 0.)));
+        let white = Color::new(1., 1., 1.);
+        let mut first = r.spawn(r.direction, r.origin);
+        let a = r.hero_weight(&mut first, 1, &white, [1., 2., 4.]);
+        let mut second = first.spawn(r.direction, r.origin);
+        let b = first.hero_weight(&mut second, 1, &white, [3., 1., 1.]);
+
+        // The path has densities of 3, 2 and 4, which average to 3
+        assert_eq!(second.hero, Some(1));
+        for i in 0..3 {
+            assert!(((a[i] * b[i]) - 1. / 3.).abs() < 1e-6);
+        }
+    }
 }
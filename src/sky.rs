@@ -0,0 +1,360 @@
+use crate::{
+    color::{Color, ColorSpace},
+    image::Image,
+    pdf::Pdf,
+    vec3::{onb::Onb, Vec3},
+};
+use std::f32::consts::{FRAC_PI_2, PI};
+
+/// Anything that can provide the radiance arriving from infinitely far away
+/// for rays that escape the scene.
+pub trait Background: Sync {
+    /// Radiance along `direction`, not including the sun
+    fn color(&self, direction: &Vec3) -> Color;
+
+    /// Backgrounds with a sun expose it so it can be sampled as a light
+    fn sun(&self) -> Option<&Sun> {
+        None
+    }
+}
+
+/// The blue-ish linear gradient used throughout the book
+pub struct Gradient {
+    horizon: Color,
+    zenith: Color,
+}
+
+impl Gradient {
+    pub fn new(horizon: Color, zenith: Color) -> Self {
+        Gradient { horizon, zenith }
+    }
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Gradient::new(Color::new(1., 1., 1.), Color::new(0.6, 0.5, 1.0))
+    }
+}
+
+impl Background for Gradient {
+    fn color(&self, direction: &Vec3) -> Color {
+        let unit_dir = direction.normalize();
+        let a = 0.5 * (unit_dir.1 + 1.);
+        &self.horizon * (1. - a) + a * &self.zenith
+    }
+}
+
+/// Converts a direction given as an elevation above the horizon and an
+/// azimuth around the y axis (both in degrees) into a unit vector. An azimuth
+/// of zero points down the negative z axis.
+pub fn direction_from_angles(elevation: f32, azimuth: f32) -> Vec3 {
+    let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+    Vec3(
+        elevation.cos() * azimuth.sin(),
+        elevation.sin(),
+        -elevation.cos() * azimuth.cos(),
+    )
+}
+
+/// A directional light with a finite angular size. The sun is treated as a
+/// disk of uniform radiance covering a cone around `direction`.
+pub struct Sun {
+    direction: Vec3,
+    cos_half_angle: f32,
+    radiance: Color,
+}
+
+impl Sun {
+    /// `angular_diameter` is in degrees (the real sun is about 0.53). The
+    /// `irradiance` is what a surface facing the sun receives, so changing
+    /// the size of the disk does not change how bright the scene is.
+    pub fn new(direction: Vec3, angular_diameter: f32, irradiance: Color) -> Self {
+        let cos_half_angle = (angular_diameter.to_radians() / 2.).cos();
+        let solid_angle = 2. * PI * (1. - cos_half_angle);
+        Sun {
+            direction: direction.normalize(),
+            cos_half_angle,
+            radiance: irradiance * (1. / solid_angle),
+        }
+    }
+
+    pub fn direction(&self) -> Vec3 {
+        self.direction
+    }
+
+    /// The radiance seen when looking along `direction`; black when the
+    /// direction misses the disk
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        if direction.normalize().dot(&self.direction) >= self.cos_half_angle {
+            self.radiance.clone()
+        } else {
+            Color::black()
+        }
+    }
+}
+
+/// Uniformly samples directions towards the sun disk
+impl Pdf for Sun {
+    fn value(&self, direction: &Vec3) -> f32 {
+        if direction.normalize().dot(&self.direction) >= self.cos_half_angle {
+            1. / (2. * PI * (1. - self.cos_half_angle))
+        } else {
+            0.
+        }
+    }
+
+    fn generate(&self) -> Vec3 {
+        Onb::new(&self.direction).transform(&Vec3::random_in_cone(self.cos_half_angle))
+    }
+}
+
+/// Coefficients of the Perez sky luminance distribution
+struct Perez([f32; 5]);
+
+impl Perez {
+    fn eval(&self, cos_theta: f32, gamma: f32) -> f32 {
+        let [a, b, c, d, e] = self.0;
+        let cos_gamma = gamma.cos();
+        (1. + a * (b / cos_theta).exp()) * (1. + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+    }
+}
+
+/// Analytic daylight model from Preetham, Shirley and Smits, "A Practical
+/// Analytic Model for Daylight" (1999). The sky is normalized so the zenith
+/// has a luminance of one regardless of the sun position.
+pub struct PreethamSky {
+    sun: Sun,
+    working_space: ColorSpace,
+    theta_sun: f32,
+    perez: [Perez; 3],
+    zenith: [f32; 3],
+}
+
+impl PreethamSky {
+    /// `turbidity` describes the amount of haze; 2 is a very clear sky while
+    /// 10 is a hazy summer day. The colors of the sky are given in
+    /// `working_space`.
+    pub fn new(sun: Sun, turbidity: f32, working_space: ColorSpace) -> Self {
+        let t = turbidity;
+        // The model is only defined for the sun above the horizon
+        let theta_sun = sun.direction().1.clamp(0., 1.).acos().min(FRAC_PI_2 - 0.01);
+
+        let perez = [
+            Perez([
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ]),
+            Perez([
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ]),
+            Perez([
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ]),
+        ];
+
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * theta_sun);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let (s, s2, s3) = (theta_sun, theta_sun * theta_sun, theta_sun.powi(3));
+        let chroma_x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+        let chroma_y = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+
+        PreethamSky {
+            sun,
+            working_space,
+            theta_sun,
+            perez,
+            zenith: [luminance, chroma_x, chroma_y],
+        }
+    }
+
+    fn sky_color(&self, direction: &Vec3) -> Color {
+        // Everything below the horizon gets the color of the horizon
+        let cos_theta = direction.1.max(0.001);
+        let gamma = direction.dot(&self.sun.direction()).clamp(-1., 1.).acos();
+
+        let mut xyy = [0f32; 3];
+        for (i, value) in xyy.iter_mut().enumerate() {
+            let perez = &self.perez[i];
+            *value = self.zenith[i] * perez.eval(cos_theta, gamma) / perez.eval(1., self.theta_sun);
+        }
+        // Normalize so the zenith luminance is one
+        let [lum, x, y] = [xyy[0] / self.zenith[0], xyy[1], xyy[2]];
+
+        self.working_space.convert_from_srgb(&xyy_to_rgb(x, y, lum))
+    }
+}
+
+impl Background for PreethamSky {
+    fn color(&self, direction: &Vec3) -> Color {
+        self.sky_color(&direction.normalize())
+    }
+
+    fn sun(&self) -> Option<&Sun> {
+        Some(&self.sun)
+    }
+}
+
+/// An equirectangular (latitude-longitude) image surrounding the scene, such
+/// as a `.hdr` panorama. The top row is straight up and the middle column
+/// looks down the negative z axis, like an azimuth of zero.
+pub struct EnvironmentMap {
+    image: Image,
+    intensity: f32,
+}
+
+impl EnvironmentMap {
+    /// `intensity` scales every pixel, since panoramas come in arbitrary
+    /// units
+    pub fn new(image: Image, intensity: f32) -> Self {
+        EnvironmentMap { image, intensity }
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> &Color {
+        &self.image.pixels[y * self.image.width + x]
+    }
+}
+
+impl Background for EnvironmentMap {
+    /// Bilinear lookup, wrapping around horizontally
+    fn color(&self, direction: &Vec3) -> Color {
+        let (width, height) = (self.image.width, self.image.height);
+        if width == 0 || height == 0 {
+            return Color::black();
+        }
+        let direction = direction.normalize();
+        let u = 0.5 + direction.0.atan2(-direction.2) / (2. * PI);
+        let v = direction.1.clamp(-1., 1.).acos() / PI;
+
+        // Pixel centers sit at half integer coordinates
+        let x = u * width as f32 - 0.5;
+        let y = (v * height as f32 - 0.5).clamp(0., (height - 1) as f32);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let column = |x: f32| (x as i64).rem_euclid(width as i64) as usize;
+        let (left, right) = (column(x0), column(x0 + 1.));
+        let (top, bottom) = (y0 as usize, (y0 as usize + 1).min(height - 1));
+
+        let upper = self.pixel(left, top) * (1. - fx) + fx * self.pixel(right, top);
+        let lower = self.pixel(left, bottom) * (1. - fx) + fx * self.pixel(right, bottom);
+        (upper * (1. - fy) + fy * lower) * self.intensity
+    }
+}
+
+/// Converts CIE xyY into linear sRGB, clamping out of gamut values to zero
+fn xyy_to_rgb(x: f32, y: f32, lum: f32) -> Color {
+    let big_x = x * lum / y;
+    let big_z = (1. - x - y) * lum / y;
+    let r = 3.2406 * big_x - 1.5372 * lum - 0.4986 * big_z;
+    let g = -0.9689 * big_x + 1.8758 * lum + 0.0415 * big_z;
+    let b = 0.0557 * big_x - 0.2040 * lum + 1.0570 * big_z;
+    Color::new(r.max(0.), g.max(0.), b.max(0.))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gradient_matches_book() {
+        let gradient = Gradient::default();
+
+        assert_eq!(gradient.color(&Vec3(0., 1., 0.)), Color::new(0.6, 0.5, 1.0));
+        assert_eq!(gradient.color(&Vec3(0., -1., 0.)), Color::new(1., 1., 1.));
+    }
+
+    #[test]
+    fn sun_disk_is_finite() {
+        let sun = Sun::new(Vec3(0., 1., 0.), 0.53, Color::new(1., 1., 1.));
+
+        assert!(sun.radiance(&Vec3(0., 1., 0.))[0] > 0.);
+        assert_eq!(sun.radiance(&Vec3(0., 1., 0.1)), Color::black());
+    }
+
+    #[test]
+    fn sun_irradiance_independent_of_size() {
+        // Radiance times solid angle should give back the irradiance
+        for diameter in [0.5, 2., 10.] {
+            let sun = Sun::new(Vec3(0., 1., 0.), diameter, Color::new(1., 1., 1.));
+            let solid_angle = 2. * PI * (1. - (diameter.to_radians() / 2.).cos());
+            let irradiance = sun.radiance(&Vec3(0., 1., 0.))[0] * solid_angle;
+            assert!((irradiance - 1.).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn sun_random_within_disk() {
+        let sun = Sun::new(Vec3(0.2, 1., 0.), 5., Color::new(1., 1., 1.));
+        for _ in 0..100 {
+            let dir = sun.generate();
+            assert!(sun.radiance(&dir)[0] > 0.);
+            assert!(sun.value(&dir) > 0.);
+        }
+    }
+
+    #[test]
+    fn preetham_zenith_normalized() {
+        let sun = Sun::new(direction_from_angles(30., 0.), 0.53, Color::black());
+        let sky = PreethamSky::new(sun, 3., ColorSpace::LinearSrgb);
+        let zenith = sky.color(&Vec3(0., 1., 0.));
+        let luminance = 0.2126 * zenith[0] + 0.7152 * zenith[1] + 0.0722 * zenith[2];
+
+        assert!((luminance - 1.).abs() < 0.05, "{luminance}");
+        // Clear skies are blue
+        assert!(zenith[2] > zenith[0]);
+    }
+
+    #[test]
+    fn preetham_brighter_towards_sun() {
+        let sun = Sun::new(direction_from_angles(20., 90.), 0.53, Color::black());
+        let sky = PreethamSky::new(sun, 3., ColorSpace::LinearSrgb);
+        let towards = sky.color(&direction_from_angles(25., 90.));
+        let away = sky.color(&direction_from_angles(25., -90.));
+
+        assert!(towards[1] > away[1]);
+    }
+
+    #[test]
+    fn environment_map_orientation() {
+        // Bright sky over a dark ground, with a red column in the middle
+        let (width, height) = (8, 4);
+        let pixels = (0..width * height)
+            .map(|i| match (i % width, i / width) {
+                (3 | 4, _) => Color::new(1., 0., 0.),
+                (_, 0 | 1) => Color::new(1., 1., 1.),
+                _ => Color::black(),
+            })
+            .collect();
+        let map = EnvironmentMap::new(
+            Image {
+                width,
+                height,
+                pixels,
+                alpha: None,
+            },
+            2.,
+        );
+
+        assert_eq!(map.color(&Vec3(0., 0.2, -1.)), Color::new(2., 0., 0.));
+        assert_eq!(map.color(&Vec3(0., 1., 0.1)), Color::new(2., 2., 2.));
+        assert_eq!(map.color(&Vec3(0., -1., 0.1)), Color::black());
+        // Wraps around behind the camera without a seam
+        let behind = map.color(&Vec3(0.001, 0.5, 1.));
+        assert_eq!(behind, map.color(&Vec3(-0.001, 0.5, 1.)));
+    }
+}
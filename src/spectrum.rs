@@ -0,0 +1,221 @@
+//! Spectral data for rendering with wavelengths instead of RGB: the CIE
+//! 1931 color matching functions, the D65 illuminant, and the conversion of
+//! RGB colors into smooth spectra from Smits, "An RGB to Spectrum Conversion
+//! for Reflectances" (1999).
+
+use crate::color::Color;
+use rand::Rng;
+use std::sync::OnceLock;
+
+/// Range of visible wavelengths in nanometers
+pub const LAMBDA_MIN: f32 = 380.;
+pub const LAMBDA_MAX: f32 = 780.;
+
+/// Dominant wavelengths of the sRGB primaries, standing in for the color
+/// channels when rendering in RGB
+pub const RGB_WAVELENGTHS: [f32; 3] = [612., 549., 465.];
+
+/// Wavelengths carried by a path. The first is the hero wavelength, and
+/// the others are rotated from it through the visible range, so every
+/// wavelength is still uniformly distributed but they cover the spectrum
+/// evenly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wavelengths(pub [f32; 3]);
+
+impl Wavelengths {
+    pub fn sample() -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = rand::thread_rng().gen::<f32>() * range;
+        Wavelengths([0., 1., 2.].map(|i| LAMBDA_MIN + (hero + i * range / 3.) % range))
+    }
+
+    /// An RGB reflectance, between zero and one, at each wavelength
+    pub fn reflectance(&self, rgb: &Color) -> Color {
+        let value = |i: usize| rgb_to_spectrum(rgb, self.0[i]);
+        Color::new(value(0), value(1), value(2))
+    }
+
+    /// An RGB light at each wavelength. White light has the spectrum of
+    /// daylight, which is the white of sRGB.
+    pub fn illuminant(&self, rgb: &Color) -> Color {
+        let value = |i: usize| rgb_to_spectrum(rgb, self.0[i]) * d65(self.0[i]);
+        Color::new(value(0), value(1), value(2))
+    }
+
+    /// Converts radiance at each wavelength into a linear sRGB estimate,
+    /// through CIE XYZ
+    pub fn to_rgb(self, radiance: &Color) -> Color {
+        // Each wavelength was drawn with a density of one over the range
+        let scale = (LAMBDA_MAX - LAMBDA_MIN) / (3. * d65_luminance());
+        let mut xyz = Color::black();
+        for (i, lambda) in self.0.iter().enumerate() {
+            xyz += cie_xyz(*lambda) * (radiance[i] * scale);
+        }
+        xyz.transform(&XYZ_TO_SRGB)
+    }
+}
+
+const XYZ_TO_SRGB: [[f32; 3]; 3] = [
+    [3.2406, -1.5372, -0.4986],
+    [-0.9689, 1.8758, 0.0415],
+    [0.0557, -0.2040, 1.0570],
+];
+
+/// The CIE 1931 standard observer, using the multi-lobe fit from Wyman,
+/// Sloan and Shirley, "Simple Analytic Approximations to the CIE XYZ Color
+/// Matching Functions" (2013)
+pub fn cie_xyz(lambda: f32) -> Color {
+    let lobe = |mu: f32, below: f32, above: f32| {
+        let sigma = if lambda < mu { below } else { above };
+        (-0.5 * ((lambda - mu) / sigma).powi(2)).exp()
+    };
+    Color::new(
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7)
+            - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    )
+}
+
+/// Relative spectral power of the CIE D65 illuminant from 380 to 780 nm in
+/// steps of 10 nm
+const D65: [f32; 41] = [
+    49.9755, 54.6482, 82.7549, 91.486, 93.4318, 86.6823, 104.865, 117.008, 117.812, 114.861,
+    115.923, 108.811, 109.354, 107.802, 104.790, 107.689, 104.405, 104.046, 100.0, 96.3342, 95.788,
+    88.6856, 90.0062, 89.5991, 87.6987, 83.2886, 83.6992, 80.0268, 80.2146, 82.2778, 78.2842,
+    69.7213, 71.6091, 74.349, 61.604, 69.8856, 75.087, 63.5927, 46.4182, 66.8054, 63.3828,
+];
+
+fn d65(lambda: f32) -> f32 {
+    interpolate(&D65, (lambda - LAMBDA_MIN) / 10.)
+}
+
+/// Luminance of the D65 spectrum, which scales it to a luminance of one
+fn d65_luminance() -> f32 {
+    static LUMINANCE: OnceLock<f32> = OnceLock::new();
+    *LUMINANCE.get_or_init(|| {
+        let steps = 4000;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f32;
+        (0..steps)
+            .map(|i| {
+                let lambda = LAMBDA_MIN + (i as f32 + 0.5) * step;
+                d65(lambda) * cie_xyz(lambda)[1] * step
+            })
+            .sum()
+    })
+}
+
+/// Linear interpolation between evenly spaced `values`, clamped at both
+/// ends
+fn interpolate(values: &[f32], at: f32) -> f32 {
+    let at = at.clamp(0., (values.len() - 1) as f32);
+    let i = (at as usize).min(values.len() - 2);
+    let t = at - i as f32;
+    values[i] * (1. - t) + values[i + 1] * t
+}
+
+/// Smits' basis spectra in ten bins of 34 nm from 380 to 720 nm
+const WHITE: [f32; 10] = [1., 1., 0.9999, 0.9993, 0.9992, 0.9998, 1., 1., 1., 1.];
+const CYAN: [f32; 10] = [
+    0.971, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0., 0., 0.,
+];
+const MAGENTA: [f32; 10] = [1., 1., 0.9685, 0.2229, 0., 0.0458, 0.8369, 1., 1., 0.9959];
+const YELLOW: [f32; 10] = [
+    0.0001, 0., 0.1088, 0.6651, 1., 1., 0.9996, 0.9586, 0.9685, 0.984,
+];
+const RED: [f32; 10] = [
+    0.1012, 0.0515, 0., 0., 0., 0., 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const GREEN: [f32; 10] = [0., 0., 0.0273, 0.7937, 1., 0.9418, 0.1719, 0., 0., 0.0025];
+const BLUE: [f32; 10] = [
+    1., 1., 0.8916, 0.3323, 0., 0., 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+/// Value at `lambda` of a smooth spectrum with the color `rgb`. The
+/// smallest channel becomes white, then the next one the matching
+/// secondary color and the rest a primary color.
+pub fn rgb_to_spectrum(rgb: &Color, lambda: f32) -> f32 {
+    // Interpolated between the centers of the bins
+    let basis = |spectrum: &[f32; 10]| interpolate(spectrum, (lambda - LAMBDA_MIN) / 34. - 0.5);
+    let [r, g, b] = [rgb[0], rgb[1], rgb[2]].map(|c| c.max(0.));
+    if r <= g && r <= b {
+        r * basis(&WHITE)
+            + if g <= b {
+                (g - r) * basis(&CYAN) + (b - g) * basis(&BLUE)
+            } else {
+                (b - r) * basis(&CYAN) + (g - b) * basis(&GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * basis(&WHITE)
+            + if r <= b {
+                (r - g) * basis(&MAGENTA) + (b - r) * basis(&BLUE)
+            } else {
+                (b - g) * basis(&MAGENTA) + (r - b) * basis(&RED)
+            }
+    } else {
+        b * basis(&WHITE)
+            + if r <= g {
+                (r - b) * basis(&YELLOW) + (g - r) * basis(&GREEN)
+            } else {
+                (g - b) * basis(&YELLOW) + (r - g) * basis(&RED)
+            }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Color of a spectrum lit by daylight, integrated with evenly spaced
+    /// wavelengths instead of random ones
+    fn integrate(spectrum: impl Fn(&Wavelengths) -> Color) -> Color {
+        let steps = 300;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / 3. / steps as f32;
+        (0..steps)
+            .map(|i| {
+                let hero = LAMBDA_MIN + (i as f32 + 0.5) * step;
+                let wavelengths = Wavelengths([0., 1., 2.].map(|k| hero + k * step * steps as f32));
+                wavelengths.to_rgb(&spectrum(&wavelengths)) * (1. / steps as f32)
+            })
+            .sum()
+    }
+
+    fn assert_close(a: &Color, b: &Color, tolerance: f32) {
+        for channel in 0..3 {
+            assert!((a[channel] - b[channel]).abs() < tolerance, "{a:?} {b:?}");
+        }
+    }
+
+    #[test]
+    fn white_light_is_white() {
+        let white = Color::new(1., 1., 1.);
+        assert_close(&integrate(|w| w.illuminant(&white)), &white, 0.01);
+    }
+
+    #[test]
+    fn reflectances_round_trip() {
+        for color in [
+            Color::new(0.73, 0.73, 0.73),
+            Color::new(0.65, 0.05, 0.05),
+            Color::new(0.12, 0.45, 0.15),
+            Color::new(0.1, 0.2, 0.5),
+            Color::new(0.8, 0.6, 0.2),
+        ] {
+            let lit = integrate(|w| w.reflectance(&color) * w.illuminant(&Color::new(1., 1., 1.)));
+            assert_close(&lit, &color, 0.03);
+        }
+    }
+
+    #[test]
+    fn wavelengths_spread_over_the_range() {
+        for _ in 0..100 {
+            let Wavelengths(lambdas) = Wavelengths::sample();
+            let mut sorted = lambdas;
+            sorted.sort_by(f32::total_cmp);
+            assert!(sorted[0] >= LAMBDA_MIN && sorted[2] < LAMBDA_MAX);
+            let third = (LAMBDA_MAX - LAMBDA_MIN) / 3.;
+            assert!((sorted[1] - sorted[0] - third).abs() < 1e-3);
+            assert!((sorted[2] - sorted[1] - third).abs() < 1e-3);
+        }
+    }
+}
@@ -2,9 +2,10 @@ use crate::{
     color::Color,
     hittable,
     material::{Lambertian, Material},
-    vec3,
+    ray::Ray,
+    vec3::{self, onb::Onb},
 };
-use std::ops::Range;
+use std::{f32::consts::PI, ops::Range};
 pub struct Sphere<M: Material> {
     center: vec3::Point3,
     radius: f32,
@@ -30,7 +31,11 @@ impl From<(f32, f32, f32, f32)> for Sphere<Lambertian> {
 }
 
 impl<M: Material> hittable::Hittable for Sphere<M> {
-    fn hit(&self, r: &crate::ray::Ray, ray_interval: &Range<f32>) -> Option<hittable::HitRecord> {
+    fn hit(
+        &self,
+        r: &crate::ray::Ray,
+        ray_interval: &Range<f32>,
+    ) -> Option<hittable::HitRecord<'_>> {
         let oc = self.center - r.origin;
         let a = r.direction.dot(&r.direction);
         let b = r.direction.dot(&oc);
@@ -63,6 +68,48 @@ impl<M: Material> hittable::Hittable for Sphere<M> {
             &self.material,
         ))
     }
+
+    fn pdf_value(&self, origin: &vec3::Point3, direction: &vec3::Vec3) -> f32 {
+        if self
+            .hit(&Ray::new(*direction, *origin), &(0.001..f32::INFINITY))
+            .is_none()
+        {
+            return 0.;
+        }
+        let dist_squared = (self.center - origin).magnitude_squared();
+        let cos_theta_max = (1. - self.radius * self.radius / dist_squared)
+            .max(0.)
+            .sqrt();
+        let solid_angle = 2. * PI * (1. - cos_theta_max);
+        1. / solid_angle
+    }
+
+    /// Samples the cone of directions subtended by the sphere
+    fn random(&self, origin: &vec3::Point3) -> vec3::Vec3 {
+        let direction = self.center - origin;
+        let dist_squared = direction.magnitude_squared();
+        let cos_theta_max = (1. - self.radius * self.radius / dist_squared)
+            .max(0.)
+            .sqrt();
+        Onb::new(&direction).transform(&vec3::Vec3::random_in_cone(cos_theta_max))
+    }
+
+    fn sample_surface(&self) -> Option<(hittable::HitRecord<'_>, f32)> {
+        let normal = vec3::Vec3::random_unit_vector();
+        let p = self.center + self.radius * normal;
+        let area = 4. * PI * self.radius * self.radius;
+        Some((
+            hittable::HitRecord::from_outside(p, normal, &self.material),
+            1. / area,
+        ))
+    }
+
+    fn surface_pdf_value(&self, origin: &vec3::Point3, direction: &vec3::Vec3) -> f32 {
+        match self.hit(&Ray::new(*direction, *origin), &(0.001..f32::INFINITY)) {
+            Some(_) => 1. / (4. * PI * self.radius * self.radius),
+            None => 0.,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -92,6 +139,28 @@ mod test {
         assert!(sphere.hit(&ray, &(0f32..1f32)).is_none());
     }
 
+    #[test]
+    fn test_random_hits_sphere() {
+        let origin = vec3::Vec3(0., 0., 0.);
+        let sphere = Sphere::from((5., 5., 5., 0.5));
+
+        for _ in 0..100 {
+            let dir = sphere.random(&origin);
+            assert!(sphere
+                .hit(&Ray::new(dir, origin), &(0f32..100f32))
+                .is_some());
+            assert!(sphere.pdf_value(&origin, &dir) > 0.);
+        }
+    }
+
+    #[test]
+    fn test_pdf_value_miss() {
+        let origin = vec3::Vec3(0., 0., 0.);
+        let sphere = Sphere::from((5., 5., 5., 0.5));
+
+        assert_eq!(sphere.pdf_value(&origin, &vec3::Vec3(-1., 0., 0.)), 0.);
+    }
+
     #[test]
     fn test_range_miss_2() {
         let origin = vec3::Vec3(0., 0., 0.);
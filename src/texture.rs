@@ -0,0 +1,152 @@
+//! Material parameters that vary over a surface. Hits carry no texture
+//! coordinates, so textures are looked up by the position in space.
+
+use crate::{
+    color::Color,
+    image::Image,
+    vec3::{Point3, Vec3},
+};
+
+pub trait Texture<T>: Send + Sync {
+    fn value(&self, p: &Point3) -> T;
+}
+
+/// Plain values are the same everywhere
+impl Texture<f32> for f32 {
+    fn value(&self, _: &Point3) -> f32 {
+        *self
+    }
+}
+
+impl Texture<Color> for Color {
+    fn value(&self, _: &Point3) -> Color {
+        self.clone()
+    }
+}
+
+impl Texture<Vec3> for Vec3 {
+    fn value(&self, _: &Point3) -> Vec3 {
+        *self
+    }
+}
+
+/// Functions of the position, for patterns made up on the spot
+impl<T, F: Fn(&Point3) -> T + Send + Sync> Texture<T> for F {
+    fn value(&self, p: &Point3) -> T {
+        self(p)
+    }
+}
+
+/// Cubes of `size` alternating between two values through space
+pub struct Checker<T> {
+    pub even: T,
+    pub odd: T,
+    pub size: f32,
+}
+
+impl<T: Clone + Send + Sync> Texture<T> for Checker<T> {
+    fn value(&self, p: &Point3) -> T {
+        let cell = |x: f32| (x / self.size).floor() as i64;
+        if (cell(p.0) + cell(p.1) + cell(p.2)) % 2 == 0 {
+            self.even.clone()
+        } else {
+            self.odd.clone()
+        }
+    }
+}
+
+/// An image, like a `.hdr` file, laid over the plane through `origin`
+/// spanned by `u` and `v` and repeated beyond them. The top left corner of
+/// the image is at `origin`, with its rows along `u` and its columns along
+/// `v`. Points off the plane take the value below or above them.
+pub struct ImageTexture {
+    image: Image,
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+}
+
+impl ImageTexture {
+    /// `u` and `v` are expected to be perpendicular
+    pub fn new(image: Image, origin: Point3, u: Vec3, v: Vec3) -> Self {
+        ImageTexture {
+            image,
+            origin,
+            u,
+            v,
+        }
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> &Color {
+        &self.image.pixels[y * self.image.width + x]
+    }
+}
+
+impl Texture<Color> for ImageTexture {
+    /// Bilinear lookup, wrapping around in both directions
+    fn value(&self, p: &Point3) -> Color {
+        let (width, height) = (self.image.width, self.image.height);
+        if width == 0 || height == 0 {
+            return Color::black();
+        }
+        let offset = p - self.origin;
+        let s = offset.dot(&self.u) / self.u.magnitude_squared();
+        let t = offset.dot(&self.v) / self.v.magnitude_squared();
+
+        // Pixel centers sit at half integer coordinates
+        let x = s * width as f32 - 0.5;
+        let y = t * height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let wrap = |i: f32, size: usize| (i as i64).rem_euclid(size as i64) as usize;
+        let (left, right) = (wrap(x0, width), wrap(x0 + 1., width));
+        let (top, bottom) = (wrap(y0, height), wrap(y0 + 1., height));
+
+        let upper = self.pixel(left, top) * (1. - fx) + fx * self.pixel(right, top);
+        let lower = self.pixel(left, bottom) * (1. - fx) + fx * self.pixel(right, bottom);
+        upper * (1. - fy) + fy * lower
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checker_alternates() {
+        let checker = Checker {
+            even: 1.,
+            odd: 0.,
+            size: 0.5,
+        };
+        assert_eq!(checker.value(&Vec3(0.1, 0.1, 0.1)), 1.);
+        assert_eq!(checker.value(&Vec3(0.6, 0.1, 0.1)), 0.);
+        assert_eq!(checker.value(&Vec3(0.6, 0.6, 0.1)), 1.);
+        // Across zero as well
+        assert_eq!(checker.value(&Vec3(-0.1, 0.1, 0.1)), 0.);
+        assert_eq!(checker.value(&Vec3(-0.1, -0.1, -0.1)), 0.);
+    }
+
+    #[test]
+    fn images_tile_the_plane() {
+        // Two pixels, black then white along x
+        let image = Image {
+            width: 2,
+            height: 1,
+            pixels: vec![Color::black(), Color::new(1., 1., 1.)],
+            alpha: None,
+        };
+        let texture =
+            ImageTexture::new(image, Vec3(0., 0., 0.), Vec3(2., 0., 0.), Vec3(0., 0., 1.));
+
+        // Pixel centers, at any height and in any tile
+        assert_eq!(texture.value(&Vec3(0.5, 0., 0.5)), Color::black());
+        assert_eq!(texture.value(&Vec3(1.5, 3., 0.2)), Color::new(1., 1., 1.));
+        assert_eq!(texture.value(&Vec3(-0.5, 0., 7.5)), Color::new(1., 1., 1.));
+        // Blended halfway between them, across the edge of a tile too
+        let middle = texture.value(&Vec3(1., 0., 0.5));
+        let edge = texture.value(&Vec3(2., 0., 0.5));
+        assert!((middle[0] - 0.5).abs() < 1e-6, "{middle:?}");
+        assert!((edge[0] - 0.5).abs() < 1e-6, "{edge:?}");
+    }
+}
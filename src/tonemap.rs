@@ -0,0 +1,242 @@
+//! Tone mapping, which squeezes the unbounded linear radiance of a render
+//! into the `0..1` range of sRGB displays before it is encoded for 8 or 16
+//! bit output. Formats that store floats keep the radiance as is.
+
+use crate::{
+    color::{Color, ColorSpace},
+    image::Image,
+};
+
+/// The curve used to bring radiance into the displayable range
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Operator {
+    /// Cuts off everything above one, like the renderer always did
+    #[default]
+    Clamp,
+    /// `L / (1 + L)` on the luminance, which never quite reaches white
+    Reinhard,
+    /// Reinhard with a luminance that maps to pure white
+    ExtendedReinhard,
+    /// John Hable's filmic curve from Uncharted 2
+    Hable,
+    /// Stephen Hill's fit of the ACES reference and sRGB output transforms
+    Aces,
+    /// Troy Sobotka's AgX, which desaturates bright colors instead of
+    /// skewing their hue
+    Agx,
+}
+
+pub struct ToneMapper {
+    pub operator: Operator,
+    /// Space the render is in, converted to linear sRGB before mapping
+    pub working_space: ColorSpace,
+    /// Exposure value in stops, so each step doubles the brightness
+    pub exposure: f32,
+    /// Luminance mapped to white by the extended Reinhard operator
+    pub white_point: f32,
+}
+
+impl Default for ToneMapper {
+    fn default() -> Self {
+        ToneMapper {
+            operator: Operator::default(),
+            working_space: ColorSpace::default(),
+            exposure: 0.,
+            white_point: 4.,
+        }
+    }
+}
+
+impl ToneMapper {
+    /// Maps a linear color of the working space to a linear sRGB color
+    /// within `0..1`
+    pub fn map(&self, color: &Color) -> Color {
+        let color = self.working_space.convert_to_srgb(color) * 2f32.powf(self.exposure);
+        let mapped = match self.operator {
+            Operator::Clamp => color,
+            Operator::Reinhard => scale_luminance(&color, |l| l / (1. + l)),
+            Operator::ExtendedReinhard => {
+                let white_squared = self.white_point * self.white_point;
+                scale_luminance(&color, |l| l * (1. + l / white_squared) / (1. + l))
+            }
+            Operator::Hable => hable(&color),
+            Operator::Aces => aces(&color),
+            Operator::Agx => agx(&color),
+        };
+        let channel = |i: usize| mapped[i].clamp(0., 1.);
+        Color::new(channel(0), channel(1), channel(2))
+    }
+
+    /// Maps every pixel of `image`. Covered pixels are mapped on their own
+    /// color, not the one premultiplied by their alpha.
+    pub fn apply(&self, image: &Image) -> Image {
+        let pixels = image
+            .pixels
+            .iter()
+            .enumerate()
+            .map(|(i, pixel)| match &image.alpha {
+                Some(alpha) if alpha[i] > 0. => self.map(&(pixel * (1. / alpha[i]))) * alpha[i],
+                Some(_) => Color::black(),
+                None => self.map(pixel),
+            })
+            .collect();
+        Image {
+            width: image.width,
+            height: image.height,
+            pixels,
+            alpha: image.alpha.clone(),
+        }
+    }
+}
+
+/// Applies `curve` to the luminance, scaling every channel alike to keep
+/// the hue
+fn scale_luminance(color: &Color, curve: impl Fn(f32) -> f32) -> Color {
+    let luminance = color.luminance();
+    if luminance <= 0. {
+        return Color::black();
+    }
+    color * (curve(luminance) / luminance)
+}
+
+fn hable(color: &Color) -> Color {
+    fn curve(x: f32) -> f32 {
+        let (a, b, c, d, e, f) = (0.15, 0.5, 0.1, 0.2, 0.02, 0.3);
+        (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+    }
+    // Linear white point and exposure bias from the talk
+    let white = curve(11.2);
+    let channel = |i: usize| curve(2. * color[i].max(0.)) / white;
+    Color::new(channel(0), channel(1), channel(2))
+}
+
+fn aces(color: &Color) -> Color {
+    // sRGB to the rendering space of the reference transform, and back
+    const INPUT: [[f32; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    const OUTPUT: [[f32; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+    let fit =
+        |x: f32| (x * (x + 0.0245786) - 0.000090537) / (x * (0.983729 * x + 0.432951) + 0.238081);
+
+    let v = color.transform(&INPUT);
+    let v = Color::new(fit(v[0]), fit(v[1]), fit(v[2]));
+    v.transform(&OUTPUT)
+}
+
+fn agx(color: &Color) -> Color {
+    // Insets the primaries so very saturated colors go to white smoothly
+    const INSET: [[f32; 3]; 3] = [
+        [0.84247906, 0.0784336, 0.079223745],
+        [0.042328242, 0.87846864, 0.07916613],
+        [0.042375655, 0.0784336, 0.879143],
+    ];
+    const OUTSET: [[f32; 3]; 3] = [
+        [1.196879, -0.09802088, -0.09902974],
+        [-0.052896852, 1.1519031, -0.098961177],
+        [-0.052971636, -0.09804345, 1.1510737],
+    ];
+    // Range of the log encoding, in stops around middle grey
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+    // Polynomial fit of the default contrast sigmoid
+    let contrast = |x: f32| {
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    };
+
+    let v = color.transform(&INSET);
+    let encode = |x: f32| {
+        let ev = x.max(1e-10).log2().clamp(MIN_EV, MAX_EV);
+        contrast((ev - MIN_EV) / (MAX_EV - MIN_EV))
+    };
+    let v = Color::new(encode(v[0]), encode(v[1]), encode(v[2])).transform(&OUTSET);
+    // The curve produces display values for a 2.2 gamma
+    let linear = |x: f32| x.max(0.).powf(2.2);
+    Color::new(linear(v[0]), linear(v[1]), linear(v[2]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const OPERATORS: [Operator; 6] = [
+        Operator::Clamp,
+        Operator::Reinhard,
+        Operator::ExtendedReinhard,
+        Operator::Hable,
+        Operator::Aces,
+        Operator::Agx,
+    ];
+
+    fn tone_mapper(operator: Operator) -> ToneMapper {
+        ToneMapper {
+            operator,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn grey_ramp_monotonic_and_bounded() {
+        for operator in OPERATORS {
+            let tone_mapper = tone_mapper(operator);
+            let mut previous = 0.;
+            for i in 0..200 {
+                let x = 0.001 * 1.08f32.powi(i);
+                let y = tone_mapper.map(&Color::new(x, x, x))[1];
+                assert!((0. ..=1.).contains(&y), "{operator:?} {x} {y}");
+                assert!(y >= previous, "{operator:?} {x} {y} {previous}");
+                previous = y;
+            }
+            assert!(tone_mapper.map(&Color::black())[0] < 0.01, "{operator:?}");
+            // Very bright greys end up close to white
+            assert!(previous > 0.9, "{operator:?} {previous}");
+        }
+    }
+
+    #[test]
+    fn reinhard_halves_one() {
+        let mapped = tone_mapper(Operator::Reinhard).map(&Color::new(1., 1., 1.));
+        assert!((mapped[0] - 0.5).abs() < 1e-5);
+
+        let tone_mapper = ToneMapper {
+            operator: Operator::ExtendedReinhard,
+            white_point: 3.,
+            ..Default::default()
+        };
+        assert!((tone_mapper.map(&Color::new(3., 3., 3.))[2] - 1.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn exposure_doubles_per_stop() {
+        let tone_mapper = ToneMapper {
+            exposure: 2.,
+            ..Default::default()
+        };
+        assert_eq!(
+            tone_mapper.map(&Color::new(0.1, 0.2, 0.5)),
+            Color::new(0.4, 0.8, 1.)
+        );
+    }
+
+    #[test]
+    fn premultiplied_pixels_mapped_unpremultiplied() {
+        let image = Image {
+            width: 2,
+            height: 1,
+            pixels: vec![Color::new(0.5, 0.5, 0.5), Color::black()],
+            alpha: Some(vec![0.5, 0.]),
+        };
+        let mapped = tone_mapper(Operator::Reinhard).apply(&image);
+        assert!((mapped.pixels[0][0] - 0.25).abs() < 1e-5);
+        assert_eq!(mapped.alpha, image.alpha);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{
+    hittable,
+    material::Material,
+    ray::Ray,
+    vec3::{self, Point3, Vec3},
+};
+use rand::Rng;
+use std::ops::Range;
+
+/// Triangle with the vertices `q`, `q + u` and `q + v`
+pub struct Triangle<M: Material> {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    normal: Vec3,
+    d: f32,
+    area: f32,
+    material: M,
+}
+
+impl<M: Material> Triangle<M> {
+    pub fn new(a: Point3, b: Point3, c: Point3, material: M) -> Self {
+        let (u, v) = (b - a, c - a);
+        let n = u.cross(&v);
+        let normal = n.normalize();
+        Triangle {
+            q: a,
+            u,
+            v,
+            w: n / n.dot(&n),
+            normal,
+            d: normal.dot(&a),
+            area: n.magnitude() / 2.,
+            material,
+        }
+    }
+}
+
+impl<M: Material> hittable::Hittable for Triangle<M> {
+    fn hit(&self, r: &Ray, ray_interval: &Range<f32>) -> Option<hittable::HitRecord<'_>> {
+        let denom = self.normal.dot(&r.direction);
+        // Parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(&r.origin)) / denom;
+        if !ray_interval.contains(&t) {
+            return None;
+        }
+
+        // Barycentric coordinates of the hit
+        let p = r.at(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if alpha < 0. || beta < 0. || alpha + beta > 1. {
+            return None;
+        }
+
+        Some(hittable::HitRecord::new(
+            p,
+            self.normal,
+            t,
+            r,
+            &self.material,
+        ))
+    }
+
+    fn pdf_value(&self, origin: &vec3::Point3, direction: &vec3::Vec3) -> f32 {
+        match self.hit(&Ray::new(*direction, *origin), &(0.001..f32::INFINITY)) {
+            Some(rec) => {
+                let dist_squared = rec.t * rec.t * direction.magnitude_squared();
+                let cosine = (direction.dot(&rec.normal) / direction.magnitude()).abs();
+                dist_squared / (cosine * self.area)
+            }
+            None => 0.,
+        }
+    }
+
+    /// Uniformly samples the area of the triangle
+    fn random(&self, origin: &vec3::Point3) -> vec3::Vec3 {
+        let mut rng = rand::thread_rng();
+        let sqrt_r1 = rng.gen::<f32>().sqrt();
+        let r2 = rng.gen::<f32>();
+        let p = self.q + (sqrt_r1 * (1. - r2)) * self.u + (sqrt_r1 * r2) * self.v;
+        p - origin
+    }
+
+    fn sample_surface(&self) -> Option<(hittable::HitRecord<'_>, f32)> {
+        let origin = Vec3(0., 0., 0.);
+        let p = self.random(&origin);
+        Some((
+            hittable::HitRecord::from_outside(p, self.normal, &self.material),
+            1. / self.area,
+        ))
+    }
+
+    fn surface_pdf_value(&self, origin: &vec3::Point3, direction: &vec3::Vec3) -> f32 {
+        match self.hit(&Ray::new(*direction, *origin), &(0.001..f32::INFINITY)) {
+            Some(_) => 1. / self.area,
+            None => 0.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{color::Color, hittable::Hittable, material::Lambertian};
+
+    fn unit_triangle() -> Triangle<Lambertian> {
+        Triangle::new(
+            Vec3(0., 0., -1.),
+            Vec3(1., 0., -1.),
+            Vec3(0., 1., -1.),
+            Lambertian::new(Color::black()),
+        )
+    }
+
+    #[test]
+    fn triangle_hit() {
+        let triangle = unit_triangle();
+        let ray = Ray::new(Vec3(0.2, 0.2, -1.), Vec3(0., 0., 0.));
+
+        assert!(triangle.hit(&ray, &(0f32..10f32)).is_some());
+    }
+
+    #[test]
+    fn triangle_miss_past_hypotenuse() {
+        let triangle = unit_triangle();
+        let ray = Ray::new(Vec3(0.6, 0.6, -1.), Vec3(0., 0., 0.));
+
+        assert!(triangle.hit(&ray, &(0f32..10f32)).is_none());
+    }
+
+    #[test]
+    fn triangle_random_hits_triangle() {
+        let triangle = unit_triangle();
+        let origin = Vec3(0.3, 0.3, 0.);
+        for _ in 0..100 {
+            let dir = triangle.random(&origin);
+            assert!(triangle
+                .hit(&Ray::new(dir, origin), &(0f32..10f32))
+                .is_some());
+            assert!(triangle.pdf_value(&origin, &dir) > 0.);
+        }
+    }
+}
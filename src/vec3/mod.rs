@@ -1,5 +1,7 @@
 use rand::{self, Rng};
-use std::{fmt, ops};
+use std::{f32::consts::PI, fmt, ops};
+
+pub mod onb;
 
 #[derive(Debug, PartialEq, Clone, Default, Copy)]
 pub struct Vec3(pub f32, pub f32, pub f32);
@@ -56,6 +58,31 @@ impl Vec3 {
         }
     }
 
+    /// Random unit vector inside the cone of directions around the z axis
+    /// whose half angle has cosine `cos_theta_max`. The directions are
+    /// uniformly distributed over the solid angle of the cone.
+    pub fn random_in_cone(cos_theta_max: f32) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let z = 1. - rng.gen::<f32>() * (1. - cos_theta_max);
+        let phi = 2. * PI * rng.gen::<f32>();
+        let sin_theta = (1. - z * z).max(0.).sqrt();
+        Vec3(phi.cos() * sin_theta, phi.sin() * sin_theta, z)
+    }
+
+    /// Random unit vector around the z axis, distributed proportionally to
+    /// the cosine with the z axis
+    pub fn random_cosine_direction() -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let r1 = rng.gen::<f32>();
+        let r2 = rng.gen::<f32>();
+        let phi = 2. * PI * r1;
+        Vec3(
+            phi.cos() * r2.sqrt(),
+            phi.sin() * r2.sqrt(),
+            (1. - r2).sqrt(),
+        )
+    }
+
     /// Method that will generate a random unit vector in the same hemisphere
     /// as the direction of the calling vector.
     pub fn random_on_hemisphere(&self) -> Vec3 {
@@ -413,6 +440,16 @@ mod tests {
         assert_eq!(input[2], 3.);
     }
 
+    #[test]
+    fn vec3_random_in_cone() {
+        let cos_theta_max = 0.9;
+        for _ in 0..100 {
+            let res = Vec3::random_in_cone(cos_theta_max);
+            assert_delta!(res.magnitude(), 1., 0.00001);
+            assert!(res.2 >= cos_theta_max - 0.00001);
+        }
+    }
+
     #[test]
     fn vec3_near_zero() {
         let tests = [
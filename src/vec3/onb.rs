@@ -0,0 +1,94 @@
+use super::Vec3;
+
+/// Orthonormal basis built around a single direction, used to move vectors
+/// sampled around the z axis into world space.
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    /// Builds a basis whose `w` axis points along `n`
+    pub fn new(n: &Vec3) -> Self {
+        let w = n.normalize();
+        let a = if w.0.abs() > 0.9 {
+            Vec3(0., 1., 0.)
+        } else {
+            Vec3(1., 0., 0.)
+        };
+        let v = w.cross(&a).normalize();
+        let u = w.cross(&v);
+        Onb { u, v, w }
+    }
+
+    /// Builds a basis whose `w` axis points along `n` and whose `u` axis
+    /// follows `tangent`, made perpendicular to `n`. Without a usable
+    /// tangent, the basis is the one `new` builds.
+    pub fn with_tangent(n: &Vec3, tangent: &Vec3) -> Self {
+        let w = n.normalize();
+        let u = *tangent - tangent.dot(&w) * w;
+        if u.magnitude_squared() < 1e-12 {
+            return Onb::new(n);
+        }
+        let u = u.normalize();
+        let v = w.cross(&u);
+        Onb { u, v, w }
+    }
+
+    /// Converts a vector expressed in this basis into world space
+    pub fn transform(&self, local: &Vec3) -> Vec3 {
+        local.0 * self.u + local.1 * self.v + local.2 * self.w
+    }
+
+    /// Converts a world space vector into this basis
+    pub fn local(&self, world: &Vec3) -> Vec3 {
+        Vec3(world.dot(&self.u), world.dot(&self.v), world.dot(&self.w))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn onb_is_orthonormal() {
+        for n in [Vec3(0., 0., 1.), Vec3(1., 0., 0.), Vec3(0.3, -2., 5.)] {
+            let onb = Onb::new(&n);
+            assert!((onb.u.magnitude() - 1.).abs() < 1e-5);
+            assert!((onb.v.magnitude() - 1.).abs() < 1e-5);
+            assert!(onb.u.dot(&onb.v).abs() < 1e-5);
+            assert!(onb.u.dot(&onb.w).abs() < 1e-5);
+            assert!(onb.v.dot(&onb.w).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn onb_transform_z_is_w() {
+        let n = Vec3(0.3, -2., 5.);
+        let onb = Onb::new(&n);
+        let res = onb.transform(&Vec3(0., 0., 1.));
+        let expected = n.normalize();
+
+        assert!((res - expected).magnitude() < 1e-5);
+        assert!((onb.local(&expected) - Vec3(0., 0., 1.)).magnitude() < 1e-5);
+
+        let v = Vec3(1., 2., -0.5);
+        assert!((onb.local(&onb.transform(&v)) - v).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn onb_follows_tangent() {
+        let n = Vec3(0., 0., 2.);
+        let onb = Onb::with_tangent(&n, &Vec3(1., 1., 3.));
+        let expected = Vec3(1., 1., 0.).normalize();
+        assert!((onb.u - expected).magnitude() < 1e-5);
+        assert!(onb.u.dot(&onb.v).abs() < 1e-5);
+        assert!((onb.transform(&Vec3(0., 0., 1.)) - n.normalize()).magnitude() < 1e-5);
+
+        // Tangents along the normal are no help
+        let onb = Onb::with_tangent(&n, &Vec3(0., 0., -1.));
+        assert!(onb.u.dot(&onb.w).abs() < 1e-5);
+        assert!((onb.u.magnitude() - 1.).abs() < 1e-5);
+    }
+}